@@ -20,6 +20,7 @@ class DashboardController extends Controller
         // минимум: карта МКС и пустые контейнеры, JWST-галерея подтянется через /api/jwst/feed
         $b     = $this->base();
         $iss   = $this->getJson($b.'/last');
+        $neo   = $this->getJson($b.'/neo/approaches', ['limit' => 1]); // сближения за ближайшие 7 дней
         $trend = []; // фронт сам заберёт /api/iss/trend (через nginx прокси)
 
         return view('dashboard', [
@@ -33,7 +34,7 @@ class DashboardController extends Controller
             'metrics' => [
                 'iss_speed' => $iss['payload']['velocity'] ?? null,
                 'iss_alt'   => $iss['payload']['altitude'] ?? null,
-                'neo_total' => 0,
+                'neo_total' => $neo['total'] ?? 0,
             ],
         ]);
     }
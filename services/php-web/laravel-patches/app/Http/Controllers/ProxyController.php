@@ -17,6 +17,11 @@ class ProxyController extends Controller
         return $this->pipe('/iss/trend' . ($q ? '?' . $q : ''));
     }
 
+    public function passes() {
+        $q = request()->getQueryString();
+        return $this->pipe('/iss/passes' . ($q ? '?' . $q : ''));
+    }
+
     private function pipe(string $path)
     {
         $url = $this->base() . $path;
@@ -0,0 +1,521 @@
+//! Оповещения о космической погоде: правила проверяются после каждой записи лент,
+//! каждое событие срабатывает по правилу один раз, история — в alerts. Доставка на
+//! вебхуки идёт через очередь alert_deliveries с повторами и подписью HMAC-SHA256.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::Sha256;
+use sqlx::{PgPool, Row};
+use tracing::{info, warn};
+
+use crate::{config::Env, donki::goes_flux};
+
+/* ---------- Настройки ---------- */
+
+/// Предел lookback_days: десять лет. Значение уходит в make_interval как INTEGER.
+const MAX_LOOKBACK_DAYS: u32 = 3650;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// Вспышка (DONKI FLR) не слабее `min_class`.
+    Flare,
+    /// CME (DONKI CME) со скоростью от `min_speed_kms`.
+    Cme,
+    /// Сближение NEO не дальше `max_miss_lunar` лунных расстояний.
+    Neo,
+    /// Геомагнитная буря (DONKI GST) с Kp от `min_kp`.
+    Storm,
+}
+
+impl AlertKind {
+    /// Какие правила проверять после записи ленты.
+    pub fn for_source(source: &str) -> Option<Self> {
+        Some(match source {
+            "flr" => Self::Flare,
+            "cme" => Self::Cme,
+            "neo" => Self::Neo,
+            "gst" => Self::Storm,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self { Self::Flare => "flare", Self::Cme => "cme", Self::Neo => "neo", Self::Storm => "storm" }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    pub kind: AlertKind,
+    pub min_class: Option<String>,
+    pub min_speed_kms: Option<f64>,
+    pub max_miss_lunar: Option<f64>,
+    /// Для `neo`: только потенциально опасные.
+    #[serde(default)]
+    pub hazardous_only: bool,
+    pub min_kp: Option<f64>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    pub name: String,
+    pub url: String,
+    /// Переменная окружения с секретом подписи; без неё запросы не подписываются.
+    pub secret_env: Option<String>,
+    /// Правила, о которых сообщать; пусто — обо всех.
+    #[serde(default)]
+    pub rules: Vec<String>,
+    #[serde(default = "default_webhook_timeout")]
+    pub timeout_seconds: u64,
+    #[serde(skip)]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct AlertsConfig {
+    pub rules: Vec<AlertRule>,
+    pub webhooks: Vec<Webhook>,
+    /// События старше этого не оповещаются: новое правило не поднимает старую историю.
+    pub lookback_days: u32,
+    /// Попыток доставки на вебхук, после — failed.
+    pub max_attempts: u32,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self { rules: Vec::new(), webhooks: Vec::new(), lookback_days: 7, max_attempts: 8 }
+    }
+}
+
+fn default_true() -> bool { true }
+fn default_webhook_timeout() -> u64 { 10 }
+
+impl AlertsConfig {
+    /// Секреты вебхуков из окружения, плюс вебхук "env" из ALERT_WEBHOOK_URL / ALERT_WEBHOOK_SECRET.
+    pub fn apply_env(&mut self, env: &Env) -> anyhow::Result<()> {
+        let var = |k: &str| env.get(k).map(str::to_string);
+        if let Some(url) = var("ALERT_WEBHOOK_URL") {
+            self.webhooks.retain(|w| w.name != "env");
+            self.webhooks.push(Webhook {
+                name: "env".into(), url,
+                secret_env: var("ALERT_WEBHOOK_SECRET").map(|_| "ALERT_WEBHOOK_SECRET".into()),
+                rules: Vec::new(), timeout_seconds: default_webhook_timeout(), secret: None,
+            });
+        }
+        for w in &mut self.webhooks {
+            w.secret = w.secret_env.as_deref().and_then(var);
+        }
+        self.validate()
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.lookback_days == 0 || self.max_attempts == 0 {
+            anyhow::bail!("alerts: lookback_days and max_attempts must be positive");
+        }
+        if self.lookback_days > MAX_LOOKBACK_DAYS {
+            anyhow::bail!("alerts: lookback_days must be at most {MAX_LOOKBACK_DAYS}");
+        }
+        for (i, r) in self.rules.iter().enumerate() {
+            if r.name.is_empty() {
+                anyhow::bail!("alert rule #{} has an empty name", i + 1);
+            }
+            if self.rules[..i].iter().any(|o| o.name == r.name) {
+                anyhow::bail!("alert rule '{}' is defined twice", r.name);
+            }
+            let needed = match r.kind {
+                AlertKind::Flare => "min_class",
+                AlertKind::Cme => "min_speed_kms",
+                AlertKind::Neo => "max_miss_lunar",
+                AlertKind::Storm => "min_kp",
+            };
+            let set = [
+                ("min_class", r.min_class.is_some()),
+                ("min_speed_kms", r.min_speed_kms.is_some()),
+                ("max_miss_lunar", r.max_miss_lunar.is_some()),
+                ("min_kp", r.min_kp.is_some()),
+            ];
+            for (field, present) in set {
+                if present != (field == needed) {
+                    let msg = if present { "is not used by" } else { "is required for" };
+                    anyhow::bail!("alert rule '{}': {field} {msg} kind '{}'", r.name, r.kind.name());
+                }
+            }
+            if let Some(c) = r.min_class.as_deref().filter(|c| goes_flux(c).is_none()) {
+                anyhow::bail!("alert rule '{}': invalid GOES class '{c}'", r.name);
+            }
+            let numbers = [r.min_speed_kms, r.max_miss_lunar, r.min_kp];
+            if numbers.iter().flatten().any(|v| !v.is_finite() || *v <= 0.0) {
+                anyhow::bail!("alert rule '{}': threshold must be a positive number", r.name);
+            }
+            if r.hazardous_only && r.kind != AlertKind::Neo {
+                anyhow::bail!("alert rule '{}': hazardous_only is only used by kind 'neo'", r.name);
+            }
+        }
+        for (i, w) in self.webhooks.iter().enumerate() {
+            if self.webhooks[..i].iter().any(|o| o.name == w.name) {
+                anyhow::bail!("webhook '{}' is defined twice", w.name);
+            }
+            reqwest::Url::parse(&w.url).with_context(|| format!("webhook '{}': invalid url '{}'", w.name, w.url))?;
+            if w.timeout_seconds == 0 {
+                anyhow::bail!("webhook '{}': timeout_seconds must be positive", w.name);
+            }
+            if let Some(env) = w.secret_env.as_deref().filter(|_| w.secret.is_none()) {
+                anyhow::bail!("webhook '{}': {env} is not set", w.name);
+            }
+            if let Some(r) = w.rules.iter().find(|r| !self.rules.iter().any(|x| &x.name == *r)) {
+                anyhow::bail!("webhook '{}': unknown rule '{r}'", w.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/* ---------- Проверка правил ---------- */
+
+/// Сработавшее правило до записи.
+struct Hit {
+    event_key: String,
+    event_at: Option<DateTime<Utc>>,
+    summary: String,
+    details: Value,
+}
+
+/// Проверяет правила, относящиеся к ленте `source`; новые оповещения записывает вместе
+/// с заданиями доставки. Возвращает число новых оповещений.
+pub async fn evaluate(pool: &PgPool, cfg: &AlertsConfig, source: &str) -> anyhow::Result<u64> {
+    let Some(kind) = AlertKind::for_source(source) else { return Ok(0) };
+    let mut created = 0;
+    for rule in cfg.rules.iter().filter(|r| r.enabled && r.kind == kind) {
+        let hits = match kind {
+            AlertKind::Flare => flare_hits(pool, rule, cfg.lookback_days).await?,
+            AlertKind::Cme => cme_hits(pool, rule, cfg.lookback_days).await?,
+            AlertKind::Neo => neo_hits(pool, rule).await?,
+            AlertKind::Storm => storm_hits(pool, rule, cfg.lookback_days).await?,
+        };
+        let webhooks: Vec<&str> = cfg.webhooks.iter()
+            .filter(|w| w.rules.is_empty() || w.rules.contains(&rule.name))
+            .map(|w| w.name.as_str())
+            .collect();
+        for hit in hits {
+            let mut tx = pool.begin().await?;
+            let id: Option<i64> = sqlx::query_scalar(
+                "INSERT INTO alerts(rule, kind, event_key, event_at, summary, details)
+                 VALUES ($1,$2,$3,$4,$5,$6)
+                 ON CONFLICT (rule, event_key) DO NOTHING RETURNING id"
+            ).bind(&rule.name).bind(kind.name()).bind(&hit.event_key).bind(hit.event_at)
+             .bind(&hit.summary).bind(&hit.details).fetch_optional(&mut *tx).await?;
+            // уже срабатывало
+            let Some(id) = id else { continue };
+            for w in &webhooks {
+                sqlx::query("INSERT INTO alert_deliveries(alert_id, webhook) VALUES ($1,$2)")
+                    .bind(id).bind(w).execute(&mut *tx).await?;
+            }
+            tx.commit().await?;
+            info!("alert {}: {}", rule.name, hit.summary);
+            created += 1;
+        }
+    }
+    Ok(created)
+}
+
+async fn flare_hits(pool: &PgPool, rule: &AlertRule, lookback_days: u32) -> anyhow::Result<Vec<Hit>> {
+    let threshold = rule.min_class.as_deref().and_then(goes_flux).map_or(f64::INFINITY, |g| g.1);
+    let rows = sqlx::query(
+        "SELECT id, start_at, peak_at, class_type, active_region, link FROM donki_events
+         WHERE event_type = 'FLR' AND class_type IS NOT NULL AND start_at >= now() - make_interval(days => $1)"
+    ).bind(lookback_days as i32).fetch_all(pool).await?;
+    Ok(rows.iter().filter_map(|r| {
+        let class: String = r.get("class_type");
+        goes_flux(&class).filter(|g| g.1 >= threshold)?;
+        let at = r.get::<Option<DateTime<Utc>>,_>("peak_at").or(r.get("start_at"));
+        let region = r.get::<Option<i32>,_>("active_region");
+        let from = region.map(|n| format!(" from AR{n}")).unwrap_or_default();
+        Some(Hit {
+            event_key: r.get("id"),
+            event_at: at,
+            summary: format!("{class} solar flare{from}"),
+            details: serde_json::json!({
+                "event_id": r.get::<String,_>("id"), "class_type": class, "active_region": region,
+                "link": r.get::<Option<String>,_>("link"),
+            }),
+        })
+    }).collect())
+}
+
+async fn cme_hits(pool: &PgPool, rule: &AlertRule, lookback_days: u32) -> anyhow::Result<Vec<Hit>> {
+    let rows = sqlx::query(
+        "SELECT id, start_at, speed_kms, source_location, link FROM donki_events
+         WHERE event_type = 'CME' AND speed_kms >= $2 AND start_at >= now() - make_interval(days => $1)"
+    ).bind(lookback_days as i32).bind(rule.min_speed_kms).fetch_all(pool).await?;
+    Ok(rows.iter().map(|r| {
+        let speed: f64 = r.get("speed_kms");
+        Hit {
+            event_key: r.get("id"),
+            event_at: r.get("start_at"),
+            summary: format!("CME at {speed:.0} km/s"),
+            details: serde_json::json!({
+                "event_id": r.get::<String,_>("id"), "speed_kms": speed,
+                "source_location": r.get::<Option<String>,_>("source_location"),
+                "link": r.get::<Option<String>,_>("link"),
+            }),
+        }
+    }).collect())
+}
+
+/// Сближения берём предстоящие и прошедшие не раньше суток назад.
+async fn neo_hits(pool: &PgPool, rule: &AlertRule) -> anyhow::Result<Vec<Hit>> {
+    let rows = sqlx::query(
+        "SELECT a.neo_id, o.name, o.hazardous, a.approach_at, a.miss_km, a.miss_lunar, a.velocity_kps
+         FROM neo_close_approaches a JOIN neo_objects o ON o.id = a.neo_id
+         WHERE a.approach_at >= now() - interval '1 day' AND a.miss_lunar <= $1 AND (o.hazardous OR NOT $2)"
+    ).bind(rule.max_miss_lunar).bind(rule.hazardous_only).fetch_all(pool).await?;
+    Ok(rows.iter().map(|r| {
+        let (id, name, at): (String, String, DateTime<Utc>) = (r.get("neo_id"), r.get("name"), r.get("approach_at"));
+        let lunar: f64 = r.get("miss_lunar");
+        let hazardous: bool = r.get("hazardous");
+        Hit {
+            event_key: format!("{id}@{}", at.to_rfc3339()),
+            event_at: Some(at),
+            summary: format!("{}NEO {name} passes at {lunar:.2} LD", if hazardous { "hazardous " } else { "" }),
+            details: serde_json::json!({
+                "neo_id": id, "name": name, "hazardous": hazardous, "miss_lunar": lunar,
+                "miss_km": r.get::<Option<f64>,_>("miss_km"), "velocity_kps": r.get::<Option<f64>,_>("velocity_kps"),
+            }),
+        }
+    }).collect())
+}
+
+async fn storm_hits(pool: &PgPool, rule: &AlertRule, lookback_days: u32) -> anyhow::Result<Vec<Hit>> {
+    let rows = sqlx::query(
+        "SELECT id, start_at, kp_max, link FROM donki_events
+         WHERE event_type = 'GST' AND kp_max >= $2 AND start_at >= now() - make_interval(days => $1)"
+    ).bind(lookback_days as i32).bind(rule.min_kp).fetch_all(pool).await?;
+    Ok(rows.iter().map(|r| {
+        let kp: f64 = r.get("kp_max");
+        Hit {
+            event_key: r.get("id"),
+            event_at: r.get("start_at"),
+            summary: format!("geomagnetic storm, Kp {kp}"),
+            details: serde_json::json!({
+                "event_id": r.get::<String,_>("id"), "kp_max": kp, "link": r.get::<Option<String>,_>("link"),
+            }),
+        }
+    }).collect())
+}
+
+/* ---------- Доставка ---------- */
+
+/// Пауза перед повтором: 1, 2, 4 ... минут, не больше часа.
+fn retry_delay(attempts: u32) -> Duration {
+    Duration::from_secs((60u64 << attempts.saturating_sub(1).min(6)).min(3600))
+}
+
+/// Тело запроса вебхука.
+fn alert_json(r: &sqlx::postgres::PgRow) -> Value {
+    serde_json::json!({
+        "id": r.get::<i64,_>("id"),
+        "rule": r.get::<String,_>("rule"),
+        "kind": r.get::<String,_>("kind"),
+        "event_key": r.get::<String,_>("event_key"),
+        "event_at": r.get::<Option<DateTime<Utc>>,_>("event_at"),
+        "summary": r.get::<String,_>("summary"),
+        "details": r.get::<Value,_>("details"),
+        "created_at": r.get::<DateTime<Utc>,_>("created_at"),
+    })
+}
+
+/// Подпись: HMAC-SHA256 от "<timestamp>.<тело>", hex.
+fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    crate::hex_encode(&mac.finalize().into_bytes())
+}
+
+/// Отправляет доставки, срок которых подошёл; возвращает число успешных.
+pub async fn deliver_due(pool: &PgPool, client: &reqwest::Client, cfg: &AlertsConfig) -> anyhow::Result<u64> {
+    let due = sqlx::query(
+        "SELECT d.id AS delivery_id, d.webhook, d.attempts, a.id, a.rule, a.kind, a.event_key, a.event_at,
+                a.summary, a.details, a.created_at
+         FROM alert_deliveries d JOIN alerts a ON a.id = d.alert_id
+         WHERE d.status = 'pending' AND d.next_attempt_at <= now()
+         ORDER BY d.next_attempt_at, d.id LIMIT 100"
+    ).fetch_all(pool).await?;
+
+    let mut delivered = 0;
+    for r in &due {
+        let id: i64 = r.get("delivery_id");
+        let name: String = r.get("webhook");
+        let attempts = r.get::<i32,_>("attempts") as u32 + 1;
+        let result = match cfg.webhooks.iter().find(|w| w.name == name) {
+            Some(w) => post(client, w, &alert_json(r)).await,
+            None => Err(anyhow::anyhow!("webhook '{name}' is no longer configured")),
+        };
+        match result {
+            Ok(()) => {
+                sqlx::query("UPDATE alert_deliveries SET status = 'delivered', attempts = $2, delivered_at = now(), last_error = NULL WHERE id = $1")
+                    .bind(id).bind(attempts as i32).execute(pool).await?;
+                delivered += 1;
+            }
+            Err(e) => {
+                let gave_up = attempts >= cfg.max_attempts;
+                warn!("alert delivery {id} to {name} failed (attempt {attempts}/{}): {e:#}", cfg.max_attempts);
+                let next = Utc::now() + chrono::Duration::from_std(retry_delay(attempts)).unwrap_or_default();
+                sqlx::query(
+                    "UPDATE alert_deliveries SET status = $2, attempts = $3, last_error = $4, next_attempt_at = $5 WHERE id = $1"
+                ).bind(id).bind(if gave_up { "failed" } else { "pending" }).bind(attempts as i32)
+                 .bind(format!("{e:#}")).bind(next).execute(pool).await?;
+            }
+        }
+    }
+    Ok(delivered)
+}
+
+/// Одна попытка: повторы ведёт очередь alert_deliveries, а не `Http` — иначе ретраи
+/// складываются, а хост вебхука попадает в предохранители внешних источников и /health.
+async fn post(client: &reqwest::Client, w: &Webhook, alert: &Value) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(alert)?;
+    let timestamp = Utc::now().timestamp();
+    let mut req = client.post(&w.url)
+        .timeout(Duration::from_secs(w.timeout_seconds))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Alert-Id", alert["id"].to_string())
+        .header("X-Alert-Timestamp", timestamp.to_string());
+    if let Some(secret) = &w.secret {
+        req = req.header("X-Alert-Signature", format!("sha256={}", sign(secret, timestamp, &body)));
+    }
+    let resp = req.body(body).send().await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("status {}", resp.status());
+    }
+    Ok(())
+}
+
+/* ---------- История ---------- */
+
+/// Последние оповещения с состоянием доставки, новые первыми.
+pub async fn history(pool: &PgPool, rule: Option<&str>, limit: i64) -> anyhow::Result<Vec<Value>> {
+    let rows = sqlx::query(
+        "SELECT a.id, a.rule, a.kind, a.event_key, a.event_at, a.summary, a.details, a.created_at,
+                COALESCE(json_agg(json_build_object(
+                    'webhook', d.webhook, 'status', d.status, 'attempts', d.attempts,
+                    'last_error', d.last_error, 'next_attempt_at', d.next_attempt_at, 'delivered_at', d.delivered_at
+                ) ORDER BY d.webhook) FILTER (WHERE d.id IS NOT NULL), '[]') AS deliveries
+         FROM alerts a LEFT JOIN alert_deliveries d ON d.alert_id = a.id
+         WHERE $1::text IS NULL OR a.rule = $1
+         GROUP BY a.id ORDER BY a.created_at DESC, a.id DESC LIMIT $2"
+    ).bind(rule).bind(limit).fetch_all(pool).await?;
+    Ok(rows.iter().map(|r| {
+        let mut v = alert_json(r);
+        v["deliveries"] = r.get::<Value,_>("deliveries");
+        v
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(text: &str) -> AlertsConfig {
+        toml::from_str(text).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        config(text).validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn source_kinds() {
+        assert_eq!(AlertKind::for_source("flr"), Some(AlertKind::Flare));
+        assert_eq!(AlertKind::for_source("cme"), Some(AlertKind::Cme));
+        assert_eq!(AlertKind::for_source("neo"), Some(AlertKind::Neo));
+        assert_eq!(AlertKind::for_source("gst"), Some(AlertKind::Storm));
+        assert_eq!(AlertKind::for_source("sep"), None);
+        assert_eq!(AlertKind::for_source("apod"), None);
+    }
+
+    #[test]
+    fn valid_rules() {
+        let cfg = config(r#"
+            [[rules]]
+            name = "m5"
+            kind = "flare"
+            min_class = "M5"
+            [[rules]]
+            name = "close"
+            kind = "neo"
+            max_miss_lunar = 2.5
+            hazardous_only = true
+            [[webhooks]]
+            name = "ops"
+            url = "https://example.org/hook"
+            rules = ["m5"]
+        "#);
+        cfg.validate().unwrap();
+        assert_eq!(cfg.lookback_days, 7);
+        assert_eq!(cfg.webhooks[0].timeout_seconds, 10);
+    }
+
+    #[test]
+    fn rule_fields_follow_kind() {
+        let missing = error("[[rules]]\nname = \"s\"\nkind = \"storm\"");
+        assert!(missing.contains("min_kp is required for kind 'storm'"), "{missing}");
+        let unused = error("[[rules]]\nname = \"c\"\nkind = \"cme\"\nmin_speed_kms = 900\nmin_kp = 5");
+        assert!(unused.contains("min_kp is not used by kind 'cme'"), "{unused}");
+        let hazardous = error("[[rules]]\nname = \"c\"\nkind = \"cme\"\nmin_speed_kms = 900\nhazardous_only = true");
+        assert!(hazardous.contains("hazardous_only"), "{hazardous}");
+        let class = error("[[rules]]\nname = \"f\"\nkind = \"flare\"\nmin_class = \"Q1\"");
+        assert!(class.contains("invalid GOES class"), "{class}");
+        let threshold = error("[[rules]]\nname = \"k\"\nkind = \"storm\"\nmin_kp = 0");
+        assert!(threshold.contains("positive"), "{threshold}");
+    }
+
+    #[test]
+    fn duplicate_names_and_unknown_rules() {
+        let rules = error("[[rules]]\nname = \"k\"\nkind = \"storm\"\nmin_kp = 5\n[[rules]]\nname = \"k\"\nkind = \"storm\"\nmin_kp = 7");
+        assert!(rules.contains("'k' is defined twice"), "{rules}");
+        let hooks = error("[[webhooks]]\nname = \"a\"\nurl = \"http://x\"\n[[webhooks]]\nname = \"a\"\nurl = \"http://y\"");
+        assert!(hooks.contains("webhook 'a' is defined twice"), "{hooks}");
+        let unknown = error("[[webhooks]]\nname = \"a\"\nurl = \"http://x\"\nrules = [\"nope\"]");
+        assert!(unknown.contains("unknown rule 'nope'"), "{unknown}");
+        let secret = error("[[webhooks]]\nname = \"a\"\nurl = \"http://x\"\nsecret_env = \"HOOK_SECRET\"");
+        assert!(secret.contains("HOOK_SECRET is not set"), "{secret}");
+    }
+
+    #[test]
+    fn lookback_is_bounded() {
+        assert!(error("lookback_days = 0").contains("positive"));
+        assert!(error("lookback_days = 3651").contains("at most 3650"));
+        config("lookback_days = 3650").validate().unwrap();
+    }
+
+    #[test]
+    fn signature_matches_reference_hmac() {
+        // HMAC-SHA256("Jefe", "1700000000.what do ya want for nothing?"), посчитано независимо
+        assert_eq!(
+            sign("Jefe", 1_700_000_000, b"what do ya want for nothing?"),
+            "1cdd0650c8be1cb0974b1788d458b1e781206cfef59b85faafc582d2e182c57e",
+        );
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_an_hour() {
+        let minutes: Vec<u64> = (1..=9).map(|n| retry_delay(n).as_secs() / 60).collect();
+        assert_eq!(minutes, [1, 2, 4, 8, 16, 32, 60, 60, 60]);
+        assert_eq!(retry_delay(0), Duration::from_secs(60));
+        assert_eq!(retry_delay(u32::MAX), Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,463 @@
+//! Конфигурация источников: sources.toml (вшитый или из SOURCES_CONFIG) плюс переопределения из окружения.
+
+use std::{collections::{BTreeMap, HashMap}, sync::OnceLock, time::Duration};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::{alerts::AlertsConfig, http::HttpPolicy, jobs::Schedule, quota::{NasaPolicy, Priority}, sources};
+
+const DEFAULT_SOURCES: &str = include_str!("../sources.toml");
+
+/* ---------- Окружение ---------- */
+
+/// Переменные, заданные самому процессу (docker-compose и т.п.), на момент старта.
+static PROCESS_ENV: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Снимок окружения: переменные процесса, поверх — .env, который их не перекрывает.
+/// .env ищется в текущем каталоге и выше, как у `dotenvy::dotenv`: из services/rust-iss
+/// находится корневой. Окружение процесса не меняется (set_var при работающих потоках небезопасен); при
+/// перезагрузке снимок собирается заново, так что удалённый из .env ключ пропадает.
+#[derive(Debug, Clone)]
+pub struct Env(HashMap<String, String>);
+
+impl Env {
+    pub fn load() -> Self {
+        let process = PROCESS_ENV.get_or_init(|| std::env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+            .collect());
+        let mut vars = process.clone();
+        if let Ok(iter) = dotenvy::dotenv_iter() {
+            for (k, v) in iter.flatten() {
+                vars.entry(k).or_insert(v);
+            }
+        }
+        Self(vars)
+    }
+
+    /// Значение как есть, в том числе пустое.
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Непустое значение.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.raw(key).filter(|v| !v.is_empty())
+    }
+}
+
+/// Предел retention_days: сто лет. Значение уходит в make_interval как INTEGER.
+const MAX_RETENTION_DAYS: u32 = 36500;
+
+/// Фоновые задачи со своим кодом; остальные имена — ленты space_cache из `sources::all()`.
+const BUILTIN: &[&str] = &["osdr", "iss", "tle"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Auth {
+    #[default]
+    None,
+    /// `api_key` из пула ключей NASA (NASA_API_KEY, NASA_API_KEYS, иначе DEMO_KEY).
+    NasaKey,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    pub url: Option<String>,
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    #[serde(default)]
+    pub auth: Auth,
+    /// Для `Auth::NasaKey`: низкий приоритет откладывается при нехватке квоты.
+    #[serde(default)]
+    pub priority: Priority,
+    /// Либо интервал, либо cron-выражение (UTC).
+    pub every_seconds: Option<u64>,
+    pub cron: Option<String>,
+    /// Случайная задержка 0..jitter к каждому запуску, чтобы источники не стреляли разом.
+    #[serde(default)]
+    pub jitter_seconds: u64,
+    #[serde(default = "default_timeout")]
+    pub timeout_seconds: u64,
+    pub retention_days: Option<u32>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_timeout() -> u64 { 30 }
+fn default_enabled() -> bool { true }
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            query: BTreeMap::new(),
+            auth: Auth::None,
+            priority: Priority::Normal,
+            every_seconds: None,
+            cron: None,
+            jitter_seconds: 0,
+            timeout_seconds: default_timeout(),
+            retention_days: None,
+            enabled: default_enabled(),
+        }
+    }
+}
+
+impl SourceConfig {
+    pub fn schedule(&self) -> anyhow::Result<Schedule> {
+        let jitter = Duration::from_secs(self.jitter_seconds);
+        match (self.every_seconds, &self.cron) {
+            (Some(0), None) => anyhow::bail!("every_seconds must be positive"),
+            (Some(s), None) => Ok(Schedule::every(Duration::from_secs(s), jitter)),
+            (None, Some(expr)) => Schedule::cron(expr, jitter),
+            _ => anyhow::bail!("set exactly one of every_seconds and cron"),
+        }
+    }
+
+    /// GET на `url` с постоянными параметрами и таймаутом источника; ключ добавляет `Http`.
+    pub fn get(&self, client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+        let mut req = client.get(url).timeout(Duration::from_secs(self.timeout_seconds));
+        if !self.query.is_empty() {
+            req = req.query(&self.query);
+        }
+        req
+    }
+
+    /// Приоритет для пула ключей NASA; `None` — источнику ключ не нужен.
+    pub fn nasa(&self) -> Option<Priority> {
+        (self.auth == Auth::NasaKey).then_some(self.priority)
+    }
+
+    /// GET на собственный `url` источника (для источников, где он обязателен).
+    pub fn request(&self, client: &reqwest::Client) -> reqwest::RequestBuilder {
+        self.get(client, self.url.as_deref().unwrap_or_default())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourcesFile {
+    http: Option<HttpPolicy>,
+    nasa: Option<NasaPolicy>,
+    alerts: Option<AlertsConfig>,
+    #[serde(default)]
+    sources: BTreeMap<String, SourceConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SourcesConfig {
+    /// Повторы и предохранители для всех внешних запросов.
+    pub http: HttpPolicy,
+    /// Ключи api.nasa.gov и запас квоты.
+    pub nasa: NasaPolicy,
+    /// Правила оповещений и вебхуки.
+    pub alerts: AlertsConfig,
+    pub osdr: SourceConfig,
+    pub iss: SourceConfig,
+    pub tle: SourceConfig,
+    /// Ленты space_cache по имени, включая выключенные.
+    pub feeds: BTreeMap<String, SourceConfig>,
+}
+
+impl SourcesConfig {
+    /// Вшитые значения (sources.toml и `Source::defaults` лент), поверх — файл из SOURCES_CONFIG
+    /// (если задан), поверх — окружение.
+    pub fn load(env: &Env) -> anyhow::Result<Self> {
+        let defaults = parse(DEFAULT_SOURCES).context("built-in sources.toml")?;
+        let mut http = defaults.http.unwrap_or_default();
+        let mut nasa = defaults.nasa.unwrap_or_default();
+        let mut alerts = defaults.alerts.unwrap_or_default();
+        let mut sources = defaults.sources;
+        for src in sources::all() {
+            sources.entry(src.name().to_string()).or_insert_with(|| src.defaults());
+        }
+        if let Some(path) = env.get("SOURCES_CONFIG") {
+            let text = std::fs::read_to_string(path).with_context(|| format!("SOURCES_CONFIG: cannot read {path}"))?;
+            let file = parse(&text).with_context(|| format!("SOURCES_CONFIG {path}"))?;
+            http = file.http.unwrap_or(http);
+            nasa = file.nasa.unwrap_or(nasa);
+            alerts = file.alerts.unwrap_or(alerts);
+            sources.extend(file.sources);
+        }
+        apply_http_env(env, &mut http)?;
+        apply_nasa_env(env, &mut nasa)?;
+        alerts.apply_env(env)?;
+
+        for (name, cfg) in sources.iter_mut() {
+            apply_env(env, name, cfg)?;
+            validate(name, cfg).with_context(|| format!("source '{name}'"))?;
+        }
+
+        let mut take = |name: &str| sources.remove(name).with_context(|| format!("source '{name}' is not configured"));
+        let (osdr, iss, tle) = (take("osdr")?, take("iss")?, take("tle")?);
+        Ok(Self { http, nasa, alerts, osdr, iss, tle, feeds: sources })
+    }
+}
+
+fn parse(text: &str) -> anyhow::Result<SourcesFile> {
+    let file: SourcesFile = toml::from_str(text)?;
+    let feeds: Vec<&str> = sources::all().iter().map(|src| src.name()).collect();
+    for name in file.sources.keys() {
+        if !BUILTIN.contains(&name.as_str()) && !feeds.contains(&name.as_str()) {
+            anyhow::bail!(
+                "unknown source '{name}' (expected one of {}, {})",
+                BUILTIN.join(", "), feeds.join(", ")
+            );
+        }
+    }
+    Ok(file)
+}
+
+/// Переменные, которыми эти значения задавались до sources.toml.
+fn legacy_env(name: &str, field: &str) -> Option<&'static str> {
+    Some(match (name, field) {
+        ("osdr", "URL") => "NASA_API_URL",
+        ("osdr", "EVERY_SECONDS") => "FETCH_EVERY_SECONDS",
+        ("iss", "EVERY_SECONDS") => "ISS_EVERY_SECONDS",
+        ("tle", "URL") => "ISS_TLE_URL",
+        ("tle", "EVERY_SECONDS") => "TLE_EVERY_SECONDS",
+        ("apod", "EVERY_SECONDS") => "APOD_EVERY_SECONDS",
+        ("neo", "EVERY_SECONDS") => "NEO_EVERY_SECONDS",
+        ("flr" | "cme", "EVERY_SECONDS") => "DONKI_EVERY_SECONDS",
+        ("spacex", "EVERY_SECONDS") => "SPACEX_EVERY_SECONDS",
+        _ => return None,
+    })
+}
+
+/// Значение из SOURCE_<ИМЯ>_<ПОЛЕ>, иначе из старой переменной; пустые строки не считаются.
+/// Исключение — пустой ISS_TLE_URL: он по-прежнему означает «только файл».
+fn env_value(env: &Env, name: &str, field: &str) -> Option<(String, String)> {
+    let key = format!("SOURCE_{}_{field}", name.to_uppercase());
+    if let Some(v) = env.get(&key) {
+        return Some((key, v.to_string()));
+    }
+    let legacy = legacy_env(name, field)?;
+    let v = env.raw(legacy)?.to_string();
+    (!v.is_empty() || legacy == "ISS_TLE_URL").then(|| (legacy.to_string(), v))
+}
+
+fn num<T: std::str::FromStr>(key: &str, v: &str) -> anyhow::Result<T> {
+    v.trim().parse().map_err(|_| anyhow::anyhow!("{key}: expected a non-negative integer, got '{v}'"))
+}
+
+/// HTTP_RETRIES, HTTP_BACKOFF_BASE_MS, HTTP_BACKOFF_MAX_SECONDS, HTTP_BREAKER_FAILURES, HTTP_BREAKER_OPEN_SECONDS,
+/// HTTP_USER_AGENT.
+fn apply_http_env(env: &Env, http: &mut HttpPolicy) -> anyhow::Result<()> {
+    let var = |k: &str| env.get(k).map(|v| (k.to_string(), v.to_string()));
+    if let Some((k, v)) = var("HTTP_RETRIES") { http.retries = num(&k, &v)?; }
+    if let Some((k, v)) = var("HTTP_BACKOFF_BASE_MS") { http.backoff_base_ms = num(&k, &v)?; }
+    if let Some((k, v)) = var("HTTP_BACKOFF_MAX_SECONDS") { http.backoff_max_seconds = num(&k, &v)?; }
+    if let Some((k, v)) = var("HTTP_BREAKER_FAILURES") { http.breaker_failures = num(&k, &v)?; }
+    if let Some((k, v)) = var("HTTP_BREAKER_OPEN_SECONDS") { http.breaker_open_seconds = num(&k, &v)?; }
+    if let Some((_, v)) = var("HTTP_USER_AGENT") { http.user_agent = Some(v); }
+    if let Some(proxy) = &http.proxy {
+        reqwest::Proxy::all(proxy).with_context(|| format!("http: invalid proxy '{proxy}'"))?;
+    }
+    Ok(())
+}
+
+/// Ключи: NASA_API_KEY, затем NASA_API_KEYS через запятую, без повторов; если нет ни одного — DEMO_KEY.
+/// Запас и пауза ключа после 429: NASA_QUOTA_RESERVE, NASA_KEY_COOLDOWN_SECONDS.
+fn apply_nasa_env(env: &Env, nasa: &mut NasaPolicy) -> anyhow::Result<()> {
+    let var = |k: &str| env.get(k).map(|v| (k.to_string(), v.to_string()));
+    if let Some((k, v)) = var("NASA_QUOTA_RESERVE") { nasa.reserve = num(&k, &v)?; }
+    if let Some((k, v)) = var("NASA_KEY_COOLDOWN_SECONDS") { nasa.cooldown_seconds = num(&k, &v)?; }
+    let listed = var("NASA_API_KEY").into_iter().chain(var("NASA_API_KEYS")).map(|(_, v)| v).collect::<Vec<_>>().join(",");
+    for key in listed.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+        if !nasa.keys.iter().any(|k| k == key) {
+            nasa.keys.push(key.to_string());
+        }
+    }
+    // без своих ключей — общий демо-ключ с жёстким лимитом
+    if nasa.keys.is_empty() {
+        nasa.keys.push("DEMO_KEY".to_string());
+    }
+    if nasa.cooldown_seconds == 0 {
+        anyhow::bail!("nasa: cooldown_seconds must be positive");
+    }
+    Ok(())
+}
+
+fn apply_env(env: &Env, name: &str, cfg: &mut SourceConfig) -> anyhow::Result<()> {
+    if let Some((_, v)) = env_value(env, name, "URL") {
+        cfg.url = Some(v).filter(|v| !v.is_empty());
+    }
+    // старые *_EVERY_SECONDS не перебивают cron из файла, SOURCE_<ИМЯ>_EVERY_SECONDS — перебивает
+    if let Some((k, v)) = env_value(env, name, "EVERY_SECONDS") {
+        if k.starts_with("SOURCE_") || cfg.cron.is_none() {
+            cfg.every_seconds = Some(num(&k, &v)?);
+            cfg.cron = None;
+        }
+    }
+    if let Some((_, v)) = env_value(env, name, "CRON") {
+        cfg.cron = Some(v);
+        cfg.every_seconds = None;
+    }
+    if let Some((k, v)) = env_value(env, name, "JITTER_SECONDS") {
+        cfg.jitter_seconds = num(&k, &v)?;
+    }
+    if let Some((k, v)) = env_value(env, name, "TIMEOUT_SECONDS") {
+        cfg.timeout_seconds = num(&k, &v)?;
+    }
+    if let Some((k, v)) = env_value(env, name, "RETENTION_DAYS") {
+        cfg.retention_days = Some(num(&k, &v)?);
+    }
+    if let Some((k, v)) = env_value(env, name, "PRIORITY") {
+        cfg.priority = match v.trim().to_lowercase().as_str() {
+            "normal" => Priority::Normal,
+            "low" => Priority::Low,
+            _ => anyhow::bail!("{k}: expected normal or low, got '{v}'"),
+        };
+    }
+    if let Some((k, v)) = env_value(env, name, "ENABLED") {
+        cfg.enabled = match v.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "0" | "false" | "no" | "off" => false,
+            _ => anyhow::bail!("{k}: expected true or false, got '{v}'"),
+        };
+    }
+    Ok(())
+}
+
+fn validate(name: &str, cfg: &SourceConfig) -> anyhow::Result<()> {
+    cfg.schedule()?;
+    if cfg.timeout_seconds == 0 {
+        anyhow::bail!("timeout_seconds must be positive");
+    }
+    if cfg.retention_days == Some(0) {
+        anyhow::bail!("retention_days must be positive (omit it to keep everything)");
+    }
+    if cfg.retention_days.is_some_and(|d| d > MAX_RETENTION_DAYS) {
+        anyhow::bail!("retention_days must be at most {MAX_RETENTION_DAYS}");
+    }
+    match name {
+        // положение берётся у провайдеров из ISS_PROVIDERS
+        "iss" if cfg.url.is_some() => anyhow::bail!("url is not used, set WHERE_ISS_URL / OPEN_NOTIFY_URL instead"),
+        // у OSDR есть история версий, старые записи не удаляем
+        "osdr" if cfg.retention_days.is_some() => anyhow::bail!("retention_days is not supported"),
+        "iss" | "tle" => {}
+        _ if cfg.url.is_none() => anyhow::bail!("url is required"),
+        _ => {}
+    }
+    if let Some(url) = &cfg.url {
+        reqwest::Url::parse(url).with_context(|| format!("invalid url '{url}'"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Env {
+        Env(vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    fn load(vars: &[(&str, &str)]) -> SourcesConfig {
+        SourcesConfig::load(&env(vars)).unwrap()
+    }
+
+    fn load_err(vars: &[(&str, &str)]) -> String {
+        format!("{:#}", SourcesConfig::load(&env(vars)).unwrap_err())
+    }
+
+    #[test]
+    fn built_in_defaults() {
+        let cfg = load(&[]);
+        assert_eq!(cfg.osdr.every_seconds, Some(600));
+        assert!(cfg.tle.url.is_some() && cfg.iss.url.is_none());
+        for src in sources::all() {
+            assert!(cfg.feeds.contains_key(src.name()), "feed {} missing", src.name());
+        }
+        assert_eq!(cfg.feeds["apod"].priority, Priority::Low);
+        assert_eq!(cfg.nasa.keys, ["DEMO_KEY"]);
+    }
+
+    #[test]
+    fn source_env_overrides() {
+        let cfg = load(&[
+            ("SOURCE_APOD_EVERY_SECONDS", "60"),
+            ("SOURCE_FLR_CRON", "0 */2 * * *"),
+            ("SOURCE_NEO_ENABLED", "off"),
+            ("SOURCE_CME_RETENTION_DAYS", "30"),
+            ("SOURCE_SEP_PRIORITY", "LOW"),
+            ("SOURCE_GST_URL", ""),
+        ]);
+        assert_eq!(cfg.feeds["apod"].every_seconds, Some(60));
+        assert_eq!((cfg.feeds["flr"].every_seconds, cfg.feeds["flr"].cron.as_deref()), (None, Some("0 */2 * * *")));
+        assert!(!cfg.feeds["neo"].enabled);
+        assert_eq!(cfg.feeds["cme"].retention_days, Some(30));
+        assert_eq!(cfg.feeds["sep"].priority, Priority::Low);
+        // пустое значение не считается
+        assert_eq!(cfg.feeds["gst"].url.as_deref(), Some("https://api.nasa.gov/DONKI/GST"));
+    }
+
+    #[test]
+    fn legacy_env_maps_to_sources() {
+        let cfg = load(&[("FETCH_EVERY_SECONDS", "120"), ("DONKI_EVERY_SECONDS", "900"), ("NASA_API_URL", "https://example.org/osdr")]);
+        assert_eq!(cfg.osdr.every_seconds, Some(120));
+        assert_eq!(cfg.osdr.url.as_deref(), Some("https://example.org/osdr"));
+        assert_eq!((cfg.feeds["flr"].every_seconds, cfg.feeds["cme"].every_seconds), (Some(900), Some(900)));
+        // SOURCE_* важнее старой переменной
+        let cfg = load(&[("FETCH_EVERY_SECONDS", "120"), ("SOURCE_OSDR_EVERY_SECONDS", "300")]);
+        assert_eq!(cfg.osdr.every_seconds, Some(300));
+        // пустой ISS_TLE_URL — только файл
+        assert_eq!(load(&[("ISS_TLE_URL", "")]).tle.url, None);
+    }
+
+    #[test]
+    fn legacy_interval_skips_cron_sources() {
+        let cron = || SourceConfig { url: Some("https://example.org".into()), cron: Some("0 * * * *".into()), ..SourceConfig::default() };
+        let mut cfg = cron();
+        apply_env(&env(&[("FETCH_EVERY_SECONDS", "120")]), "osdr", &mut cfg).unwrap();
+        assert_eq!((cfg.every_seconds, cfg.cron.as_deref()), (None, Some("0 * * * *")));
+        let mut cfg = cron();
+        apply_env(&env(&[("SOURCE_OSDR_EVERY_SECONDS", "120")]), "osdr", &mut cfg).unwrap();
+        assert_eq!((cfg.every_seconds, cfg.cron), (Some(120), None));
+    }
+
+    #[test]
+    fn bad_env_values_are_errors() {
+        assert!(load_err(&[("SOURCE_APOD_EVERY_SECONDS", "often")]).contains("SOURCE_APOD_EVERY_SECONDS"));
+        assert!(load_err(&[("SOURCE_APOD_PRIORITY", "urgent")]).contains("expected normal or low"));
+        assert!(load_err(&[("SOURCE_APOD_ENABLED", "maybe")]).contains("expected true or false"));
+        assert!(load_err(&[("SOURCE_APOD_EVERY_SECONDS", "0")]).contains("every_seconds must be positive"));
+        assert!(load_err(&[("SOURCE_APOD_CRON", "nonsense")]).contains("source 'apod'"));
+        assert!(load_err(&[("NASA_KEY_COOLDOWN_SECONDS", "0")]).contains("cooldown_seconds"));
+    }
+
+    #[test]
+    fn nasa_keys_from_env() {
+        let cfg = load(&[("NASA_API_KEY", "a"), ("NASA_API_KEYS", "b, a ,,c"), ("NASA_QUOTA_RESERVE", "5")]);
+        assert_eq!(cfg.nasa.keys, ["a", "b", "c"]);
+        assert_eq!(cfg.nasa.reserve, 5);
+    }
+
+    #[test]
+    fn validate_rules() {
+        let base = || SourceConfig { url: Some("https://example.org".into()), every_seconds: Some(60), ..SourceConfig::default() };
+        validate("apod", &base()).unwrap();
+        let err = |name: &str, cfg: SourceConfig| validate(name, &cfg).unwrap_err().to_string();
+        assert!(err("apod", SourceConfig { timeout_seconds: 0, ..base() }).contains("timeout_seconds"));
+        assert!(err("apod", SourceConfig { retention_days: Some(0), ..base() }).contains("retention_days must be positive"));
+        assert!(err("apod", SourceConfig { retention_days: Some(MAX_RETENTION_DAYS + 1), ..base() }).contains("at most"));
+        assert!(err("apod", SourceConfig { cron: Some("0 * * * *".into()), ..base() }).contains("exactly one"));
+        assert!(err("apod", SourceConfig { url: None, ..base() }).contains("url is required"));
+        assert!(err("apod", SourceConfig { url: Some("not a url".into()), ..base() }).contains("invalid url"));
+        assert!(err("iss", base()).contains("url is not used"));
+        assert!(err("osdr", SourceConfig { retention_days: Some(30), ..base() }).contains("not supported"));
+        validate("tle", &SourceConfig { url: None, ..base() }).unwrap();
+    }
+
+    #[test]
+    fn file_replaces_sources_and_rejects_unknown_names() {
+        let file = parse("[sources.apod]\nurl = \"https://example.org/apod\"\ncron = \"0 6 * * *\"").unwrap();
+        assert_eq!(file.sources["apod"].cron.as_deref(), Some("0 6 * * *"));
+        assert_eq!(file.sources["apod"].auth, Auth::None);
+        let err = parse("[sources.nope]\nurl = \"https://example.org\"").err().unwrap().to_string();
+        assert!(err.contains("unknown source 'nope'") && err.contains("apod"), "{err}");
+        assert!(parse("[sources.apod]\nevery = 5").is_err());
+    }
+}
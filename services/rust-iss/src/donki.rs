@@ -0,0 +1,447 @@
+//! События космической погоды DONKI в donki_events по их собственным идентификаторам,
+//! уведомления — в donki_notifications. Окна выборок перекрываются, поэтому запись
+//! идемпотентна; linkedEvents — в donki_links.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+
+/// Поле с идентификатором события: у вспышек flrID, у бурь gstID и т.д.
+const ID_FIELDS: &[&str] = &["activityID", "flrID", "gstID", "sepID", "hssID", "rbeID", "mpcID"];
+
+/// Тип события по идентификатору вида 2024-05-10T06:27:00-CME-001.
+pub fn event_type(id: &str) -> Option<&str> {
+    let mut parts = id.rsplit('-');
+    parts.next()?;
+    parts.next().filter(|t| !t.is_empty() && t.chars().all(|c| c.is_ascii_uppercase()))
+}
+
+/// DONKI пишет время без секунд: 2024-05-10T06:27Z.
+fn time(v: &Value) -> Option<DateTime<Utc>> {
+    let s = v.as_str()?.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Some(t.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%MZ").ok().map(|t| t.and_utc())
+}
+
+#[derive(Debug)]
+struct Event {
+    id: String,
+    kind: String,
+    start_at: Option<DateTime<Utc>>,
+    peak_at: Option<DateTime<Utc>>,
+    end_at: Option<DateTime<Utc>>,
+    class_type: Option<String>,
+    source_location: Option<String>,
+    active_region: Option<i32>,
+    speed_kms: Option<f64>,
+    /// Максимальный Kp геомагнитной бури.
+    kp_max: Option<f64>,
+    link: Option<String>,
+    linked: Vec<String>,
+    payload: Value,
+}
+
+fn parse_event(kind: &str, v: &Value) -> Option<Event> {
+    let id = ID_FIELDS.iter().find_map(|k| v.get(*k)?.as_str())?.to_string();
+    let start = ["beginTime", "startTime", "eventTime"].iter().find_map(|k| time(&v[*k]));
+    // у CME скорость — в анализах; берём помеченный самым точным
+    let analyses = v["cmeAnalyses"].as_array();
+    let best = analyses.and_then(|a| a.iter().find(|x| x["isMostAccurate"] == true).or(a.first()));
+    let linked = v["linkedEvents"].as_array().map(|a| {
+        a.iter().filter_map(|l| l["activityID"].as_str()).map(str::to_string).collect()
+    });
+    Some(Event {
+        kind: event_type(&id).unwrap_or(kind).to_string(),
+        id,
+        start_at: start,
+        peak_at: time(&v["peakTime"]),
+        end_at: time(&v["endTime"]),
+        class_type: v["classType"].as_str().map(str::to_string),
+        source_location: v["sourceLocation"].as_str().filter(|s| !s.is_empty()).map(str::to_string),
+        active_region: v["activeRegionNum"].as_i64().map(|n| n as i32),
+        speed_kms: best.and_then(|a| a["speed"].as_f64()),
+        kp_max: v["allKpValues"].as_array()
+            .and_then(|a| a.iter().filter_map(|k| k["kpIndex"].as_f64()).reduce(f64::max)),
+        link: v["link"].as_str().map(str::to_string),
+        linked: linked.unwrap_or_default(),
+        payload: v.clone(),
+    })
+}
+
+/// Записывает массив событий одного типа (`kind` — FLR, CME, ...); возвращает число
+/// новых или изменившихся событий.
+pub async fn store_events(pool: &PgPool, kind: &str, events: &Value) -> anyhow::Result<u64> {
+    let mut written = 0;
+    let mut tx = pool.begin().await?;
+    for e in events.as_array().into_iter().flatten().filter_map(|v| parse_event(kind, v)) {
+        let changed = sqlx::query(
+            "INSERT INTO donki_events(id, event_type, start_at, peak_at, end_at, class_type, source_location,
+                                      active_region, speed_kms, kp_max, link, payload)
+             VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12)
+             ON CONFLICT (id) DO UPDATE SET
+                event_type = EXCLUDED.event_type, start_at = EXCLUDED.start_at, peak_at = EXCLUDED.peak_at,
+                end_at = EXCLUDED.end_at, class_type = EXCLUDED.class_type,
+                source_location = EXCLUDED.source_location, active_region = EXCLUDED.active_region,
+                speed_kms = EXCLUDED.speed_kms, kp_max = EXCLUDED.kp_max, link = EXCLUDED.link, payload = EXCLUDED.payload, updated_at = now()
+             WHERE donki_events.payload IS DISTINCT FROM EXCLUDED.payload"
+        ).bind(&e.id).bind(&e.kind).bind(e.start_at).bind(e.peak_at).bind(e.end_at)
+         .bind(&e.class_type).bind(&e.source_location).bind(e.active_region).bind(e.speed_kms)
+         .bind(e.kp_max).bind(&e.link).bind(&e.payload)
+         .execute(&mut *tx).await?.rows_affected();
+        if changed == 0 {
+            continue;
+        }
+        written += changed;
+        // связи — как в последней версии события
+        sqlx::query("DELETE FROM donki_links WHERE from_id = $1 AND NOT (to_id = ANY($2))")
+            .bind(&e.id).bind(&e.linked).execute(&mut *tx).await?;
+        for to in &e.linked {
+            sqlx::query("INSERT INTO donki_links(from_id, to_id) VALUES ($1,$2) ON CONFLICT DO NOTHING")
+                .bind(&e.id).bind(to).execute(&mut *tx).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(written)
+}
+
+/// Записывает ответ /DONKI/notifications; возвращает число новых уведомлений.
+/// Уведомления не правятся задним числом — повторы просто пропускаются.
+pub async fn store_notifications(pool: &PgPool, items: &Value) -> anyhow::Result<u64> {
+    let mut written = 0;
+    let mut tx = pool.begin().await?;
+    for n in items.as_array().into_iter().flatten() {
+        let Some(id) = n["messageID"].as_str() else { continue };
+        written += sqlx::query(
+            "INSERT INTO donki_notifications(id, message_type, issued_at, url, body)
+             VALUES ($1,$2,$3,$4,$5) ON CONFLICT (id) DO NOTHING"
+        ).bind(id).bind(n["messageType"].as_str().unwrap_or("unknown")).bind(time(&n["messageIssueTime"]))
+         .bind(n["messageURL"].as_str()).bind(n["messageBody"].as_str())
+         .execute(&mut *tx).await?.rows_affected();
+    }
+    tx.commit().await?;
+    Ok(written)
+}
+
+/* ---------- Запросы ---------- */
+
+const EVENT_COLUMNS: &str = "id, event_type, start_at, peak_at, end_at, class_type, source_location,
+    active_region, speed_kms, kp_max, link";
+
+fn event_json(r: &sqlx::postgres::PgRow) -> Value {
+    serde_json::json!({
+        "id": r.get::<String,_>("id"),
+        "type": r.get::<String,_>("event_type"),
+        "start_at": r.get::<Option<DateTime<Utc>>,_>("start_at"),
+        "peak_at": r.get::<Option<DateTime<Utc>>,_>("peak_at"),
+        "end_at": r.get::<Option<DateTime<Utc>>,_>("end_at"),
+        "class_type": r.get::<Option<String>,_>("class_type"),
+        "source_location": r.get::<Option<String>,_>("source_location"),
+        "active_region": r.get::<Option<i32>,_>("active_region"),
+        "speed_kms": r.get::<Option<f64>,_>("speed_kms"),
+        "kp_max": r.get::<Option<f64>,_>("kp_max"),
+        "link": r.get::<Option<String>,_>("link"),
+    })
+}
+
+/// Событие с исходным ответом DONKI и связанными событиями в обе стороны; `None` — неизвестно.
+/// Связанные, которых ещё нет в базе (другой тип или за пределами окна), отдаются только с id и типом.
+pub async fn event(pool: &PgPool, id: &str) -> anyhow::Result<Option<Value>> {
+    let Some(row) = sqlx::query(&format!("SELECT {EVENT_COLUMNS}, payload FROM donki_events WHERE id = $1"))
+        .bind(id).fetch_optional(pool).await? else {
+        return Ok(None);
+    };
+    let mut out = event_json(&row);
+    out["payload"] = row.get::<Value,_>("payload");
+
+    let linked_ids: Vec<String> = sqlx::query_scalar(
+        "SELECT to_id FROM donki_links WHERE from_id = $1
+         UNION SELECT from_id FROM donki_links WHERE to_id = $1"
+    ).bind(id).fetch_all(pool).await?;
+    let known = sqlx::query(&format!(
+        "SELECT {EVENT_COLUMNS} FROM donki_events WHERE id = ANY($1) ORDER BY start_at NULLS LAST, id"
+    )).bind(&linked_ids).fetch_all(pool).await?;
+
+    let mut linked: Vec<Value> = known.iter().map(event_json).collect();
+    let missing: Vec<Value> = linked_ids.iter()
+        .filter(|l| !linked.iter().any(|k| k["id"] == l.as_str()))
+        .map(|l| serde_json::json!({ "id": l, "type": event_type(l), "known": false }))
+        .collect();
+    linked.extend(missing);
+    out["linked_events"] = linked.into();
+    Ok(Some(out))
+}
+
+/// Окно и фильтр для списков событий и уведомлений.
+pub struct TimeFilter {
+    /// У событий — FLR, GST, ...; у уведомлений — messageType в нижнем регистре.
+    pub types: Vec<String>,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub limit: i64,
+}
+
+/// События выбранных типов (все, если список пуст) с началом в окне, новые первыми,
+/// и общее число подходящих.
+pub async fn events(pool: &PgPool, q: &TimeFilter) -> anyhow::Result<(i64, Vec<Value>)> {
+    let rows = sqlx::query(&format!(
+        "SELECT {EVENT_COLUMNS}, count(*) OVER () AS total FROM donki_events
+         WHERE start_at >= $1 AND start_at < $2 AND (cardinality($3::text[]) = 0 OR event_type = ANY($3))
+         ORDER BY start_at DESC, id LIMIT $4"
+    )).bind(q.from).bind(q.to).bind(&q.types).bind(q.limit).fetch_all(pool).await?;
+    let total = rows.first().map_or(0, |r| r.get::<i64,_>("total"));
+    Ok((total, rows.iter().map(event_json).collect()))
+}
+
+pub async fn notifications(pool: &PgPool, q: &TimeFilter) -> anyhow::Result<(i64, Vec<Value>)> {
+    let rows = sqlx::query(
+        "SELECT id, message_type, issued_at, url, body, count(*) OVER () AS total FROM donki_notifications
+         WHERE issued_at >= $1 AND issued_at < $2 AND (cardinality($3::text[]) = 0 OR lower(message_type) = ANY($3))
+         ORDER BY issued_at DESC, id LIMIT $4"
+    ).bind(q.from).bind(q.to).bind(&q.types).bind(q.limit).fetch_all(pool).await?;
+    let total = rows.first().map_or(0, |r| r.get::<i64,_>("total"));
+    Ok((total, rows.iter().map(|r| serde_json::json!({
+        "id": r.get::<String,_>("id"),
+        "type": r.get::<String,_>("message_type"),
+        "issued_at": r.get::<Option<DateTime<Utc>>,_>("issued_at"),
+        "url": r.get::<Option<String>,_>("url"),
+        "body": r.get::<Option<String>,_>("body"),
+    })).collect()))
+}
+
+/* ---------- Статистика вспышек ---------- */
+
+/// Классы GOES и нижняя граница потока 1–8 Å каждого, Вт/м².
+const GOES_CLASSES: &[(char, f64)] = &[('A', 1e-8), ('B', 1e-7), ('C', 1e-6), ('M', 1e-5), ('X', 1e-4)];
+
+/// Пиковый поток по классу GOES: "X1.2" → 1.2e-4, "M5" → 5e-5; буква без числа — множитель 1.
+pub fn goes_flux(class: &str) -> Option<(char, f64)> {
+    let class = class.trim();
+    let letter = class.chars().next()?.to_ascii_uppercase();
+    let base = GOES_CLASSES.iter().find(|(c, _)| *c == letter)?.1;
+    let rest = &class[1..];
+    let mult = if rest.is_empty() { 1.0 } else { rest.parse::<f64>().ok().filter(|m| m.is_finite() && *m > 0.0)? };
+    Some((letter, mult * base))
+}
+
+/// Обратно к записи класса: 1.2e-4 → "X1.2". Множитель округляется до десятых, и если
+/// округление дало 10 — это уже следующий класс: 9.96e-5 → "X1.0", а не "M10.0".
+fn goes_class(flux: f64) -> String {
+    let round = |m: f64| (m * 10.0).round() / 10.0;
+    let i = GOES_CLASSES.iter().rposition(|(_, b)| flux >= *b).unwrap_or(0);
+    let (mut letter, mut base) = GOES_CLASSES[i];
+    if round(flux / base) >= 10.0 {
+        if let Some(&next) = GOES_CLASSES.get(i + 1) {
+            (letter, base) = next;
+        }
+    }
+    format!("{letter}{:.1}", round(flux / base))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Bucket { Day, Week, Month }
+
+impl Bucket {
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "day" => Self::Day,
+            "week" => Self::Week,
+            "month" => Self::Month,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self { Self::Day => "day", Self::Week => "week", Self::Month => "month" }
+    }
+
+    /// Начало интервала, в который попадает `t`; недели — с понедельника.
+    fn start(self, t: DateTime<Utc>) -> chrono::NaiveDate {
+        use chrono::Datelike;
+        let d = t.date_naive();
+        match self {
+            Self::Day => d,
+            Self::Week => d - chrono::Days::new(d.weekday().num_days_from_monday() as u64),
+            Self::Month => d.with_day(1).unwrap_or(d),
+        }
+    }
+
+    fn next(self, d: chrono::NaiveDate) -> chrono::NaiveDate {
+        match self {
+            Self::Day => d + chrono::Days::new(1),
+            Self::Week => d + chrono::Days::new(7),
+            Self::Month => d + chrono::Months::new(1),
+        }
+    }
+}
+
+/// Границы корзин длительности, минуты.
+const DURATION_BINS: &[i64] = &[10, 30, 60, 120, 240];
+const TOP_REGIONS: usize = 10;
+
+#[derive(Default)]
+struct BucketStats {
+    count: u64,
+    max_flux: Option<f64>,
+    by_class: BTreeMap<char, u64>,
+}
+
+/// Сводка вспышек (FLR) с началом в окне: число по классам, ряд пикового потока по
+/// интервалам, самые активные области и распределение длительностей.
+pub async fn flare_stats(pool: &PgPool, from: DateTime<Utc>, to: DateTime<Utc>, bucket: Bucket) -> anyhow::Result<Value> {
+    let rows = sqlx::query(
+        "SELECT start_at, end_at, class_type, active_region FROM donki_events
+         WHERE event_type = 'FLR' AND start_at >= $1 AND start_at < $2 ORDER BY start_at"
+    ).bind(from).bind(to).fetch_all(pool).await?;
+
+    let mut classes: BTreeMap<char, u64> = GOES_CLASSES.iter().map(|(c, _)| (*c, 0)).collect();
+    let mut unclassified = 0;
+    let mut series: BTreeMap<chrono::NaiveDate, BucketStats> = BTreeMap::new();
+    let mut regions: HashMap<i32, (u64, f64)> = HashMap::new();
+    let mut durations: Vec<i64> = Vec::new();
+
+    // пустые интервалы тоже в ряду — на графике видны паузы
+    let mut d = bucket.start(from);
+    while d.and_hms_opt(0, 0, 0).is_some_and(|t| t.and_utc() < to) {
+        series.entry(d).or_default();
+        d = bucket.next(d);
+    }
+
+    for r in &rows {
+        let start: DateTime<Utc> = r.get("start_at");
+        let b = series.entry(bucket.start(start)).or_default();
+        b.count += 1;
+        let goes = r.get::<Option<String>,_>("class_type").as_deref().and_then(goes_flux);
+        match goes {
+            Some((letter, flux)) => {
+                *classes.entry(letter).or_default() += 1;
+                *b.by_class.entry(letter).or_default() += 1;
+                b.max_flux = Some(b.max_flux.map_or(flux, |m| m.max(flux)));
+            }
+            None => unclassified += 1,
+        }
+        if let Some(region) = r.get::<Option<i32>,_>("active_region") {
+            let e = regions.entry(region).or_insert((0, 0.0));
+            e.0 += 1;
+            e.1 = e.1.max(goes.map_or(0.0, |g| g.1));
+        }
+        if let Some(end) = r.get::<Option<DateTime<Utc>>,_>("end_at") {
+            let minutes = (end - start).num_minutes();
+            if minutes >= 0 {
+                durations.push(minutes);
+            }
+        }
+    }
+
+    let series: Vec<Value> = series.into_iter().map(|(start, b)| serde_json::json!({
+        "start": start,
+        "count": b.count,
+        "max_flux_wm2": b.max_flux,
+        "max_class": b.max_flux.map(goes_class),
+        "classes": b.by_class.iter().map(|(c, n)| (c.to_string(), Value::from(*n))).collect::<serde_json::Map<_, _>>(),
+    })).collect();
+
+    let mut regions: Vec<(i32, (u64, f64))> = regions.into_iter().collect();
+    regions.sort_by(|a, b| b.1.0.cmp(&a.1.0).then(b.1.1.total_cmp(&a.1.1)).then(a.0.cmp(&b.0)));
+    let regions: Vec<Value> = regions.into_iter().take(TOP_REGIONS).map(|(region, (count, flux))| serde_json::json!({
+        "active_region": region,
+        "count": count,
+        "max_class": (flux > 0.0).then(|| goes_class(flux)),
+    })).collect();
+
+    Ok(serde_json::json!({
+        "from": from,
+        "to": to,
+        "bucket": bucket.name(),
+        "total": rows.len(),
+        "unclassified": unclassified,
+        "classes": classes.iter().map(|(c, n)| (c.to_string(), Value::from(*n))).collect::<serde_json::Map<_, _>>(),
+        "series": series,
+        "active_regions": regions,
+        "durations_minutes": duration_stats(durations),
+    }))
+}
+
+fn duration_stats(mut d: Vec<i64>) -> Value {
+    if d.is_empty() {
+        return serde_json::json!({ "count": 0 });
+    }
+    d.sort_unstable();
+    let pct = |p: f64| d[((d.len() - 1) as f64 * p).round() as usize];
+    let mut histogram = Vec::new();
+    let mut lower = 0;
+    for &upper in DURATION_BINS.iter().chain([i64::MAX].iter()) {
+        let count = d.iter().filter(|m| **m >= lower && **m < upper).count();
+        histogram.push(serde_json::json!({
+            "from": lower,
+            "to": (upper != i64::MAX).then_some(upper),
+            "count": count,
+        }));
+        lower = upper;
+    }
+    serde_json::json!({
+        "count": d.len(),
+        "min": d[0],
+        "median": pct(0.5),
+        "p90": pct(0.9),
+        "max": d[d.len() - 1],
+        "mean": d.iter().sum::<i64>() as f64 / d.len() as f64,
+        "histogram": histogram,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= b.abs() * 1e-12
+    }
+
+    #[test]
+    fn flux_from_class() {
+        let (c, f) = goes_flux("X1.2").unwrap();
+        assert!(c == 'X' && close(f, 1.2e-4));
+        let (c, f) = goes_flux("M5").unwrap();
+        assert!(c == 'M' && close(f, 5e-5));
+        let (c, f) = goes_flux("m5.0").unwrap();
+        assert!(c == 'M' && close(f, 5e-5));
+        // буква без числа — множитель 1
+        let (c, f) = goes_flux("X").unwrap();
+        assert!(c == 'X' && close(f, 1e-4));
+    }
+
+    #[test]
+    fn flux_rejects_garbage() {
+        assert_eq!(goes_flux(""), None);
+        assert_eq!(goes_flux("Z1"), None);
+        assert_eq!(goes_flux("M-1"), None);
+        assert_eq!(goes_flux("Mx"), None);
+    }
+
+    #[test]
+    fn class_from_flux() {
+        assert_eq!(goes_class(1.2e-4), "X1.2");
+        assert_eq!(goes_class(5e-5), "M5.0");
+        assert_eq!(goes_class(3.4e-6), "C3.4");
+        assert_eq!(goes_class(2.5e-3), "X25.0");
+        assert_eq!(goes_class(5e-9), "A0.5");
+    }
+
+    #[test]
+    fn class_rounding_moves_to_next_letter() {
+        assert_eq!(goes_class(9.96e-5), "X1.0");
+        assert_eq!(goes_class(9.94e-5), "M9.9");
+        assert_eq!(goes_class(9.99e-6), "M1.0");
+    }
+
+    #[test]
+    fn class_round_trips() {
+        for c in ["A1.0", "B3.3", "C9.9", "M1.0", "M5.2", "X1.0", "X9.3"] {
+            assert_eq!(goes_class(goes_flux(c).unwrap().1), c);
+        }
+    }
+}
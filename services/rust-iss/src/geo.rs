@@ -0,0 +1,331 @@
+//! Геометрия наземной трассы МКС: расстояния, прореживание и разрезание по антимеридиану.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+/// Угловая скорость вращения Земли (звёздные сутки), °/с.
+pub const EARTH_ROTATION_DEG_PER_S: f64 = 360.985_647_4 / 86_400.0;
+
+/// Точка трассы в том виде, в каком она уходит клиенту.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackPoint {
+    pub t: DateTime<Utc>,
+    pub lat: f64,
+    pub lon: f64,
+    pub altitude_km: Option<f64>,
+    pub velocity_kmh: Option<f64>,
+}
+
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let rlat1 = lat1.to_radians();
+    let rlat2 = lat2.to_radians();
+    let dlat = (lat2 - lat1).to_radians();
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + rlat1.cos() * rlat2.cos() * (dlon / 2.0).sin().powi(2);
+    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+    EARTH_RADIUS_KM * c
+}
+
+/// Долготы без скачков на ±180: каждая следующая отличается от предыдущей не больше чем на 180°.
+fn unwrap_lons(points: &[TrackPoint]) -> Vec<f64> {
+    let mut out: Vec<f64> = Vec::with_capacity(points.len());
+    for (i, p) in points.iter().enumerate() {
+        let lon = match i {
+            0 => p.lon,
+            _ => {
+                let mut d = p.lon - points[i - 1].lon;
+                if d > 180.0 { d -= 360.0 }
+                if d < -180.0 { d += 360.0 }
+                out[i - 1] + d
+            }
+        };
+        out.push(lon);
+    }
+    out
+}
+
+/// Largest-Triangle-Three-Buckets по плоскости (долгота без скачков, широта).
+/// Первая и последняя точки сохраняются, порядок по времени не меняется.
+pub fn lttb(mut points: Vec<TrackPoint>, threshold: usize) -> Vec<TrackPoint> {
+    let n = points.len();
+    if threshold >= n || n <= 2 {
+        return points;
+    }
+    // треугольников нет: остаются только концы
+    if threshold < 3 {
+        points.drain(1..n - 1);
+        return points;
+    }
+    let xs = unwrap_lons(&points);
+    let ys: Vec<f64> = points.iter().map(|p| p.lat).collect();
+
+    let mut keep = Vec::with_capacity(threshold);
+    keep.push(0usize);
+    let every = (n - 2) as f64 / (threshold - 2) as f64;
+    let mut a = 0usize;
+    for i in 0..threshold - 2 {
+        // среднее следующего бакета — третья вершина треугольника
+        let next_start = ((i + 1) as f64 * every) as usize + 1;
+        let next_end = (((i + 2) as f64 * every) as usize + 1).min(n);
+        let cnt = (next_end - next_start).max(1) as f64;
+        let (avg_x, avg_y) = (next_start..next_end)
+            .fold((0.0, 0.0), |(sx, sy), j| (sx + xs[j], sy + ys[j]));
+        let (avg_x, avg_y) = (avg_x / cnt, avg_y / cnt);
+
+        let start = (i as f64 * every) as usize + 1;
+        let end = (((i + 1) as f64 * every) as usize + 1).min(n - 1);
+        let mut best = start;
+        let mut best_area = -1.0;
+        for j in start..end {
+            let area = ((xs[a] - avg_x) * (ys[j] - ys[a]) - (xs[a] - xs[j]) * (avg_y - ys[a])).abs();
+            if area > best_area {
+                best_area = area;
+                best = j;
+            }
+        }
+        keep.push(best);
+        a = best;
+    }
+    keep.push(n - 1);
+
+    let mut points: Vec<Option<TrackPoint>> = points.into_iter().map(Some).collect();
+    keep.into_iter().filter_map(|i| points[i].take()).collect()
+}
+
+/// Режет трассу на отрезки там, где она пересекает антимеридиан, добавляя на концах
+/// интерполированные точки на ±180°, чтобы полилинии на карте доходили до края.
+pub fn split_antimeridian(points: Vec<TrackPoint>) -> Vec<Vec<TrackPoint>> {
+    let mut segments = Vec::new();
+    let mut cur: Vec<TrackPoint> = Vec::new();
+    for p in points {
+        if let Some(prev) = cur.last() {
+            let d = p.lon - prev.lon;
+            if d.abs() > 180.0 {
+                // шаг через антимеридиан: долгота p в системе отсчёта prev
+                let (edge, lon2) = if d < 0.0 { (180.0, p.lon + 360.0) } else { (-180.0, p.lon - 360.0) };
+                let f = (edge - prev.lon) / (lon2 - prev.lon);
+                let lat = prev.lat + (p.lat - prev.lat) * f;
+                let dt = (p.t - prev.t).num_milliseconds() as f64 * f;
+                let t = prev.t + chrono::Duration::milliseconds(dt as i64);
+                let lerp = |a: Option<f64>, b: Option<f64>| match (a, b) {
+                    (Some(a), Some(b)) => Some(a + (b - a) * f),
+                    _ => None,
+                };
+                let altitude_km = lerp(prev.altitude_km, p.altitude_km);
+                let velocity_kmh = lerp(prev.velocity_kmh, p.velocity_kmh);
+                cur.push(TrackPoint { t, lat, lon: edge, altitude_km, velocity_kmh });
+                segments.push(std::mem::take(&mut cur));
+                cur.push(TrackPoint { t, lat, lon: -edge, altitude_km, velocity_kmh });
+            }
+        }
+        cur.push(p);
+    }
+    if !cur.is_empty() {
+        segments.push(cur);
+    }
+    segments
+}
+
+/// Отрезки, из которых получается линия: в LineString нужно хотя бы две позиции (RFC 7946, 3.1.4).
+fn drawable(segments: &[Vec<TrackPoint>]) -> impl Iterator<Item = &Vec<TrackPoint>> {
+    segments.iter().filter(|s| s.len() >= 2)
+}
+
+/// GeoJSON FeatureCollection: линия трассы (LineString или MultiLineString после разреза,
+/// null — если линии не набралось) и точки замеров со временем, высотой и скоростью в свойствах.
+pub fn to_geojson(segments: &[Vec<TrackPoint>], points: &[TrackPoint]) -> serde_json::Value {
+    let lines: Vec<Vec<[f64; 2]>> = drawable(segments)
+        .map(|s| s.iter().map(|p| [p.lon, p.lat]).collect())
+        .collect();
+    let geometry = match lines.as_slice() {
+        [] => serde_json::Value::Null,
+        [one] => serde_json::json!({ "type": "LineString", "coordinates": one }),
+        _ => serde_json::json!({ "type": "MultiLineString", "coordinates": lines }),
+    };
+    let mut features = vec![serde_json::json!({
+        "type": "Feature",
+        "geometry": geometry,
+        "properties": {
+            "name": "ISS ground track",
+            "start": points.first().map(|p| p.t),
+            "end": points.last().map(|p| p.t),
+        }
+    })];
+    features.extend(points.iter().map(|p| serde_json::json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": [p.lon, p.lat] },
+        "properties": {
+            "timestamp": p.t,
+            "altitude_km": p.altitude_km,
+            "velocity_kmh": p.velocity_kmh,
+        }
+    })));
+    serde_json::json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// KML 2.2 для Google Earth: MultiGeometry из отрезков трассы (без неё, если линии не набралось)
+/// и папка точек с TimeStamp.
+pub fn to_kml(segments: &[Vec<TrackPoint>], points: &[TrackPoint]) -> String {
+    use std::fmt::Write;
+
+    let mut k = String::new();
+    k.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    k.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n<name>ISS ground track</name>\n");
+    k.push_str("<Style id=\"track\"><LineStyle><color>ff00a5ff</color><width>2</width></LineStyle></Style>\n");
+    if drawable(segments).next().is_some() {
+        k.push_str("<Placemark>\n<name>Track</name>\n<styleUrl>#track</styleUrl>\n<MultiGeometry>\n");
+        for seg in drawable(segments) {
+            k.push_str("<LineString><tessellate>1</tessellate><coordinates>");
+            for p in seg {
+                let _ = write!(k, "{},{},0 ", p.lon, p.lat);
+            }
+            k.push_str("</coordinates></LineString>\n");
+        }
+        k.push_str("</MultiGeometry>\n</Placemark>\n");
+    }
+    k.push_str("<Folder>\n<name>Samples</name>\n");
+    for p in points {
+        let when = p.t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let _ = write!(k, "<Placemark><TimeStamp><when>{when}</when></TimeStamp><ExtendedData>");
+        if let Some(a) = p.altitude_km {
+            let _ = write!(k, "<Data name=\"altitude_km\"><value>{a}</value></Data>");
+        }
+        if let Some(v) = p.velocity_kmh {
+            let _ = write!(k, "<Data name=\"velocity_kmh\"><value>{v}</value></Data>");
+        }
+        let _ = writeln!(k, "</ExtendedData><Point><coordinates>{},{},0</coordinates></Point></Placemark>", p.lon, p.lat);
+    }
+    k.push_str("</Folder>\n</Document>\n</kml>\n");
+    k
+}
+
+/// Начальный азимут (0° — север, по часовой) на большом круге из точки 1 в точку 2.
+pub fn initial_bearing_deg(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (p1, p2) = (lat1.to_radians(), lat2.to_radians());
+    let dl = (lon2 - lon1).to_radians();
+    let y = dl.sin() * p2.cos();
+    let x = p1.cos() * p2.sin() - p1.sin() * p2.cos() * dl.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pt(sec: i64, lat: f64, lon: f64) -> TrackPoint {
+        TrackPoint {
+            t: DateTime::from_timestamp(1_700_000_000 + sec, 0).unwrap(),
+            lat, lon, altitude_km: Some(420.0), velocity_kmh: Some(27_600.0),
+        }
+    }
+
+    #[test]
+    fn geojson_drops_single_point_segments() {
+        let segments = vec![vec![pt(0, 0.0, 179.0)], vec![pt(60, 1.0, -179.0), pt(120, 2.0, -175.0)]];
+        let points: Vec<TrackPoint> = segments.concat();
+        let g = to_geojson(&segments, &points);
+        let track = &g["features"][0]["geometry"];
+        assert_eq!(track["type"], "LineString");
+        assert_eq!(track["coordinates"], serde_json::json!([[-179.0, 1.0], [-175.0, 2.0]]));
+        // точки замеров остаются все
+        assert_eq!(g["features"].as_array().unwrap().len(), 1 + points.len());
+    }
+
+    #[test]
+    fn geojson_without_a_line_has_null_geometry() {
+        let g = to_geojson(&[], &[]);
+        assert!(g["features"][0]["geometry"].is_null());
+        assert_eq!(g["features"].as_array().unwrap().len(), 1);
+
+        let one = vec![pt(0, 10.0, 20.0)];
+        let g = to_geojson(std::slice::from_ref(&one), &one);
+        assert!(g["features"][0]["geometry"].is_null());
+        assert_eq!(g["features"][1]["geometry"]["coordinates"], serde_json::json!([20.0, 10.0]));
+    }
+
+    #[test]
+    fn geojson_multilinestring_after_split() {
+        let segments = split_antimeridian(vec![pt(0, 0.0, 178.0), pt(60, 1.0, 179.5), pt(120, 2.0, -179.0)]);
+        let g = to_geojson(&segments, &[]);
+        assert_eq!(g["features"][0]["geometry"]["type"], "MultiLineString");
+        assert_eq!(g["features"][0]["geometry"]["coordinates"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn kml_skips_short_segments_and_empty_tracks() {
+        let segments = vec![vec![pt(0, 0.0, 179.0)], vec![pt(60, 1.0, -179.0), pt(120, 2.0, -175.0)]];
+        let k = to_kml(&segments, &segments.concat());
+        assert_eq!(k.matches("<LineString>").count(), 1);
+        assert!(k.contains("<coordinates>-179,1,0 -175,2,0 </coordinates>"));
+        assert_eq!(k.matches("<Point>").count(), 3);
+        assert!(k.contains("<when>2023-11-14T22:13:20Z</when>"));
+
+        let empty = to_kml(&[], &[]);
+        assert!(!empty.contains("<MultiGeometry>"));
+        assert!(empty.contains("<Folder>") && empty.ends_with("</kml>\n"));
+    }
+
+    fn times(points: &[TrackPoint]) -> Vec<i64> {
+        points.iter().map(|p| p.t.timestamp() - 1_700_000_000).collect()
+    }
+
+    fn zigzag(n: i64) -> Vec<TrackPoint> {
+        (0..n).map(|i| pt(i * 60, if i % 7 == 3 { 40.0 } else { (i as f64 * 0.3).sin() * 10.0 }, -170.0 + i as f64 * 3.0)).collect()
+    }
+
+    #[test]
+    fn lttb_below_three_keeps_endpoints() {
+        for threshold in [0, 1, 2] {
+            assert_eq!(times(&lttb(zigzag(10), threshold)), [0, 540], "threshold {threshold}");
+        }
+        // концов меньше двух не бывает
+        assert_eq!(times(&lttb(zigzag(1), 0)), [0]);
+        assert_eq!(times(&lttb(zigzag(2), 1)), [0, 60]);
+        assert!(lttb(Vec::new(), 0).is_empty());
+    }
+
+    #[test]
+    fn lttb_at_or_above_len_is_identity() {
+        assert_eq!(times(&lttb(zigzag(10), 10)), times(&zigzag(10)));
+        assert_eq!(times(&lttb(zigzag(10), 500)), times(&zigzag(10)));
+    }
+
+    #[test]
+    fn lttb_picks_peaks_in_time_order() {
+        let out = lttb(zigzag(100), 20);
+        let t = times(&out);
+        assert_eq!(t.len(), 20);
+        assert_eq!((t[0], t[19]), (0, 99 * 60));
+        assert!(t.windows(2).all(|w| w[0] < w[1]));
+        // выбросы на 40° — самые большие треугольники
+        assert!(out.iter().filter(|p| p.lat == 40.0).count() >= 10);
+    }
+
+    #[test]
+    fn split_at_antimeridian_crossing() {
+        let segments = split_antimeridian(vec![pt(0, 10.0, 179.0), pt(60, 12.0, -179.0)]);
+        assert_eq!(segments.len(), 2);
+        let (a, b) = (&segments[0], &segments[1]);
+        assert_eq!((a.len(), b.len()), (2, 2));
+        assert_eq!((a[1].lon, b[0].lon), (180.0, -180.0));
+        // середина пути по широте и времени
+        assert!((a[1].lat - 11.0).abs() < 1e-9 && a[1].lat == b[0].lat);
+        assert_eq!(a[1].t.timestamp() - 1_700_000_000, 30);
+        assert_eq!(b[1].lon, -179.0);
+    }
+
+    #[test]
+    fn split_keeps_westward_path_without_crossing() {
+        let segments = split_antimeridian(vec![pt(0, 10.0, 179.0), pt(60, 11.0, 170.0), pt(120, 12.0, 160.0)]);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].len(), 3);
+        assert!(split_antimeridian(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn unwrapped_longitudes_are_continuous() {
+        let pts = vec![pt(0, 0.0, 178.0), pt(60, 0.0, -178.0), pt(120, 0.0, -174.0)];
+        assert_eq!(unwrap_lons(&pts), [178.0, 182.0, 186.0]);
+    }
+}
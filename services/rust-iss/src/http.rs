@@ -0,0 +1,505 @@
+//! Общий HTTP-слой для внешних источников: один клиент с пулом соединений, повторы
+//! с экспоненциальной задержкой и джиттером, учёт Retry-After, автомат-предохранитель
+//! (circuit breaker) на каждый хост, условные запросы по ETag/Last-Modified и ключи api.nasa.gov.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, RwLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER},
+    Request, RequestBuilder, Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::quota::{NasaKeys, NasaPolicy, Priority, QuotaError};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct HttpPolicy {
+    /// Повторов после первой попытки.
+    pub retries: u32,
+    pub backoff_base_ms: u64,
+    pub backoff_max_seconds: u64,
+    /// Сколько неудач подряд размыкает предохранитель хоста.
+    pub breaker_failures: u32,
+    /// Сколько хост стоит разомкнутым до пробного запроса.
+    pub breaker_open_seconds: u64,
+    /// По умолчанию — rust_iss/<версия>.
+    pub user_agent: Option<String>,
+    /// HTTP(S)-прокси для всех внешних запросов.
+    pub proxy: Option<String>,
+}
+
+impl Default for HttpPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3, backoff_base_ms: 500, backoff_max_seconds: 30,
+            breaker_failures: 5, breaker_open_seconds: 60,
+            user_agent: None, proxy: None,
+        }
+    }
+}
+
+const DEFAULT_USER_AGENT: &str = concat!("rust_iss/", env!("CARGO_PKG_VERSION"), " (space dashboard collector)");
+
+impl HttpPolicy {
+    fn client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut b = reqwest::Client::builder()
+            .user_agent(self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+            .connect_timeout(Duration::from_secs(10))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(4)
+            .tcp_keepalive(Duration::from_secs(60));
+        if let Some(proxy) = &self.proxy {
+            b = b.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(b.build()?)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HttpError {
+    #[error("circuit open for {host} until {until}")]
+    CircuitOpen { host: String, until: DateTime<Utc> },
+    /// Без URL: в query бывает api_key.
+    #[error(transparent)]
+    Request(reqwest::Error),
+    #[error(transparent)]
+    Quota(#[from] QuotaError),
+}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Request(e.without_url())
+    }
+}
+
+/* ---------- Предохранитель ---------- */
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BreakerState {
+    Closed,
+    Open,
+    /// Срок истёк, пропущен один пробный запрос.
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Breaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    open_until: Option<DateTime<Utc>>,
+    last_failure_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Self { state: BreakerState::Closed, consecutive_failures: 0, open_until: None, last_failure_at: None, last_error: None }
+    }
+}
+
+impl Breaker {
+    /// Пропускает запрос, если предохранитель замкнут; по истечении срока — один пробный.
+    /// Отказ — со временем, до которого хост закрыт.
+    fn admit(&mut self, now: DateTime<Utc>, policy: &HttpPolicy) -> Result<(), DateTime<Utc>> {
+        if policy.breaker_failures == 0 {
+            return Ok(());
+        }
+        let until = self.open_until.unwrap_or(now);
+        match self.state {
+            BreakerState::Closed => Ok(()),
+            // пробный запрос; если он потерялся, через тот же срок пускаем следующий
+            BreakerState::Open | BreakerState::HalfOpen if now >= until => {
+                self.state = BreakerState::HalfOpen;
+                self.open_until = Some(now + chrono::Duration::seconds(policy.breaker_open_seconds as i64));
+                Ok(())
+            }
+            BreakerState::Open | BreakerState::HalfOpen => Err(until),
+        }
+    }
+
+    /// Учитывает исход запроса; `true` — предохранитель только что разомкнулся.
+    fn record(&mut self, now: DateTime<Utc>, policy: &HttpPolicy, failure: Option<&str>) -> bool {
+        let Some(error) = failure else {
+            self.state = BreakerState::Closed;
+            self.consecutive_failures = 0;
+            self.open_until = None;
+            return false;
+        };
+        self.consecutive_failures += 1;
+        self.last_failure_at = Some(now);
+        self.last_error = Some(error.to_string());
+        let tripped = policy.breaker_failures > 0
+            && (self.state == BreakerState::HalfOpen || self.consecutive_failures >= policy.breaker_failures);
+        if !tripped {
+            return false;
+        }
+        let opened = self.state != BreakerState::Open;
+        self.state = BreakerState::Open;
+        self.open_until = Some(now + chrono::Duration::seconds(policy.breaker_open_seconds as i64));
+        opened
+    }
+}
+
+/* ---------- Клиент ---------- */
+
+pub struct Http {
+    pool: PgPool,
+    policy: RwLock<HttpPolicy>,
+    /// Один на процесс: пул соединений и TLS-сессии переживают опросы. Пересобирается,
+    /// только если при перезагрузке сменились user_agent или proxy.
+    client: RwLock<reqwest::Client>,
+    breakers: Mutex<BTreeMap<String, Breaker>>,
+    nasa: NasaKeys,
+}
+
+/// Неудачная попытка, после которой имеет смысл повторить.
+enum Transient {
+    Status(Response),
+    Network(reqwest::Error),
+}
+
+pub struct Validators {
+    key: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Http {
+    pub fn new(pool: PgPool, policy: HttpPolicy, nasa: NasaPolicy) -> anyhow::Result<Self> {
+        let http = Self {
+            pool,
+            client: RwLock::new(policy.client()?),
+            policy: RwLock::new(policy),
+            breakers: Mutex::default(),
+            nasa: NasaKeys::default(),
+        };
+        http.nasa.configure(nasa);
+        Ok(http)
+    }
+
+    pub fn configure(&self, policy: HttpPolicy, nasa: NasaPolicy) -> anyhow::Result<()> {
+        let old = self.policy();
+        if old.user_agent != policy.user_agent || old.proxy != policy.proxy {
+            *self.client.write().unwrap_or_else(|e| e.into_inner()) = policy.client()?;
+        }
+        *self.policy.write().unwrap_or_else(|e| e.into_inner()) = policy;
+        self.nasa.configure(nasa);
+        Ok(())
+    }
+
+    pub fn nasa(&self) -> &NasaKeys {
+        &self.nasa
+    }
+
+    fn policy(&self) -> HttpPolicy {
+        self.policy.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Общий клиент для сборки запросов; отправлять через `send`/`send_conditional`.
+    pub fn client(&self) -> reqwest::Client {
+        self.client.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn with_breakers<T>(&self, f: impl FnOnce(&mut BTreeMap<String, Breaker>) -> T) -> T {
+        f(&mut self.breakers.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Состояние предохранителей по хостам для /health.
+    pub fn breakers(&self) -> BTreeMap<String, Breaker> {
+        self.with_breakers(|b| b.clone())
+    }
+
+    pub fn any_open(&self) -> bool {
+        self.with_breakers(|b| b.values().any(|b| b.state != BreakerState::Closed))
+    }
+
+    /// Отправляет запрос с повторами. Ответ с кодом ошибки возвращается как есть, когда
+    /// повторы кончились или код не временный — проверка статуса остаётся за вызывающим.
+    pub async fn send(&self, req: RequestBuilder) -> Result<Response, HttpError> {
+        let (client, request) = req.build_split();
+        self.execute(&client, request?, None).await
+    }
+
+    /// Как `send`, но с If-None-Match/If-Modified-Since от прошлого ответа на тот же URL.
+    /// `None` — 304, у источника ничего не поменялось; валидаторы нового ответа сохранить
+    /// через `remember` после успешной записи. С `nasa` к запросу добавляется api_key из пула ключей.
+    pub async fn send_conditional(&self, req: RequestBuilder, nasa: Option<Priority>)
+    -> Result<Option<(Response, Validators)>, HttpError> {
+        let (client, request) = req.build_split();
+        let mut request = request?;
+        let key = validator_key(request.url());
+        match self.load_validators(&key).await {
+            Ok(Some((etag, last_modified))) => {
+                let h = request.headers_mut();
+                if let Some(v) = etag.and_then(|v| v.parse().ok()) { h.insert(IF_NONE_MATCH, v); }
+                if let Some(v) = last_modified.and_then(|v| v.parse().ok()) { h.insert(IF_MODIFIED_SINCE, v); }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("cannot load validators for {key}: {e}"),
+        }
+        let resp = self.execute(&client, request, nasa).await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let validators = Validators {
+            key,
+            etag: header_str(resp.headers(), ETAG),
+            last_modified: header_str(resp.headers(), LAST_MODIFIED),
+        };
+        Ok(Some((resp, validators)))
+    }
+
+    /// Запоминает валидаторы ответа, данные которого уже записаны.
+    pub async fn remember(&self, v: Validators) -> anyhow::Result<()> {
+        if v.etag.is_none() && v.last_modified.is_none() {
+            return Ok(());
+        }
+        sqlx::query(
+            "INSERT INTO http_validators(url, etag, last_modified, updated_at) VALUES ($1,$2,$3,now())
+             ON CONFLICT (url) DO UPDATE SET etag = EXCLUDED.etag, last_modified = EXCLUDED.last_modified, updated_at = now()"
+        ).bind(&v.key).bind(&v.etag).bind(&v.last_modified).execute(&self.pool).await?;
+        // в URL лент бывают даты — старые адреса больше не запросят
+        sqlx::query("DELETE FROM http_validators WHERE updated_at < now() - interval '30 days'")
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn load_validators(&self, key: &str) -> anyhow::Result<Option<(Option<String>, Option<String>)>> {
+        Ok(sqlx::query_as("SELECT etag, last_modified FROM http_validators WHERE url = $1")
+            .bind(key).fetch_optional(&self.pool).await?)
+    }
+
+    async fn execute(&self, client: &reqwest::Client, request: Request, nasa: Option<Priority>)
+    -> Result<Response, HttpError> {
+        let policy = self.policy();
+        let host = request.url().host_str().unwrap_or_default().to_string();
+
+        let mut attempt = 0;
+        loop {
+            self.admit(&host, &policy)?;
+            let key = match nasa {
+                Some(priority) => self.nasa.acquire(priority)?,
+                None => None,
+            };
+            // запрос с потоковым телом не клонируется — одна попытка
+            let Some(this) = request.try_clone() else {
+                let resp = client.execute(with_key(request, key.as_deref())).await;
+                self.record(&host, &policy, resp.as_ref().err().map(|_| "request failed"));
+                return Ok(resp?);
+            };
+            let failed = match client.execute(with_key(this, key.as_deref())).await {
+                // 429 по ключу NASA — лимит ключа, а не сбой хоста: пробуем следующий ключ
+                Ok(resp) if key.as_deref().is_some_and(|k| self.nasa.observe(k, resp.status(), resp.headers())) => {
+                    self.record(&host, &policy, None);
+                    continue;
+                }
+                Ok(resp) if key.is_some() && resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    self.record(&host, &policy, None);
+                    return Ok(resp);
+                }
+                Ok(resp) if is_transient(resp.status()) => Transient::Status(resp),
+                Ok(resp) => {
+                    self.record(&host, &policy, None);
+                    return Ok(resp);
+                }
+                Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => Transient::Network(e.without_url()),
+                Err(e) => {
+                    // хост ответил, но что-то не так с ответом — предохранитель не при чём
+                    self.record(&host, &policy, None);
+                    return Err(e.into());
+                }
+            };
+
+            let (error, after) = match &failed {
+                Transient::Status(resp) => (format!("status {}", resp.status()), retry_after(resp.headers(), Utc::now())),
+                Transient::Network(e) => (e.to_string(), None),
+            };
+            self.record(&host, &policy, Some(&error));
+
+            let Some(delay) = retry_delay(&policy, attempt, after) else {
+                return match failed {
+                    Transient::Status(resp) => Ok(resp),
+                    Transient::Network(e) => Err(HttpError::Request(e)),
+                };
+            };
+            warn!("{host}: {error}, retry {}/{} in {delay:?}", attempt + 1, policy.retries);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    fn admit(&self, host: &str, policy: &HttpPolicy) -> Result<(), HttpError> {
+        if policy.breaker_failures == 0 {
+            return Ok(());
+        }
+        self.with_breakers(|all| all.entry(host.to_string()).or_default().admit(Utc::now(), policy))
+            .map_err(|until| HttpError::CircuitOpen { host: host.to_string(), until })
+    }
+
+    fn record(&self, host: &str, policy: &HttpPolicy, failure: Option<&str>) {
+        let opened = self.with_breakers(|all| {
+            let b = all.entry(host.to_string()).or_default();
+            b.record(Utc::now(), policy, failure).then_some(b.consecutive_failures)
+        });
+        if let Some(failures) = opened {
+            warn!("{host}: circuit open for {}s after {failures} failures", policy.breaker_open_seconds);
+        }
+    }
+}
+
+fn with_key(mut request: Request, key: Option<&str>) -> Request {
+    if let Some(key) = key {
+        request.url_mut().query_pairs_mut().append_pair("api_key", key);
+    }
+    request
+}
+
+/// URL без api_key: ключ в базе не храним.
+fn validator_key(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    let pairs: Vec<(String, String)> = url.query_pairs()
+        .filter(|(k, _)| k != "api_key")
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    if pairs.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+fn header_str(h: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    h.get(name)?.to_str().ok().map(str::to_string)
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// Retry-After в секундах или HTTP-датой.
+fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let v = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = v.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(v).ok()?.with_timezone(&Utc);
+    Some((at - now).to_std().unwrap_or_default())
+}
+
+/// Пауза перед повтором после неудачной попытки `attempt` (с нуля); `None` — повторов больше нет.
+fn retry_delay(policy: &HttpPolicy, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+    if attempt >= policy.retries {
+        return None;
+    }
+    let delay = retry_after.unwrap_or_else(|| backoff(policy, attempt));
+    // Retry-After длиннее потолка — не ждём, пусть решает планировщик
+    (delay <= Duration::from_secs(policy.backoff_max_seconds)).then_some(delay)
+}
+
+/// Потолок задержки попытки, мс: min(max, base·2^attempt).
+fn backoff_cap_ms(policy: &HttpPolicy, attempt: u32) -> u64 {
+    let cap = policy.backoff_max_seconds.saturating_mul(1000);
+    policy.backoff_base_ms.saturating_mul(1u64 << attempt.min(20)).min(cap)
+}
+
+/// Экспоненциальная задержка с полным джиттером: случайно в 0..=`backoff_cap_ms`.
+fn backoff(policy: &HttpPolicy, attempt: u32) -> Duration {
+    Duration::from_millis(rand::thread_rng().gen_range(0..=backoff_cap_ms(policy, attempt)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(breaker_failures: u32) -> HttpPolicy {
+        HttpPolicy { retries: 3, backoff_base_ms: 500, backoff_max_seconds: 30, breaker_failures, breaker_open_seconds: 60, ..HttpPolicy::default() }
+    }
+
+    fn at(sec: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + sec, 0).unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let p = policy(5);
+        let caps: Vec<u64> = (0..8).map(|a| backoff_cap_ms(&p, a)).collect();
+        assert_eq!(caps, [500, 1000, 2000, 4000, 8000, 16000, 30000, 30000]);
+        assert_eq!(backoff_cap_ms(&p, u32::MAX), 30000);
+        for a in 0..8 {
+            assert!(backoff(&p, a) <= Duration::from_millis(caps[a as usize]));
+        }
+    }
+
+    #[test]
+    fn retry_delay_respects_retries_and_retry_after() {
+        let p = policy(5);
+        assert!(retry_delay(&p, 0, None).is_some_and(|d| d <= Duration::from_millis(500)));
+        assert_eq!(retry_delay(&p, 2, Some(Duration::from_secs(7))), Some(Duration::from_secs(7)));
+        assert_eq!(retry_delay(&p, 3, Some(Duration::from_secs(1))), None);
+        // Retry-After длиннее backoff_max_seconds — не повторяем
+        assert_eq!(retry_delay(&p, 0, Some(Duration::from_secs(31))), None);
+        assert_eq!(retry_delay(&p, 0, Some(Duration::from_secs(30))), Some(Duration::from_secs(30)));
+        let none = HttpPolicy { retries: 0, ..p };
+        assert_eq!(retry_delay(&none, 0, None), None);
+    }
+
+    #[test]
+    fn retry_after_seconds_and_dates() {
+        let headers = |v: &str| HeaderMap::from_iter([(RETRY_AFTER, v.parse().unwrap())]);
+        assert_eq!(retry_after(&headers(" 120 "), at(0)), Some(Duration::from_secs(120)));
+        // 2023-11-14 22:13:20 UTC — это at(0)
+        assert_eq!(retry_after(&headers("Tue, 14 Nov 2023 22:14:05 GMT"), at(0)), Some(Duration::from_secs(45)));
+        assert_eq!(retry_after(&headers("Tue, 14 Nov 2023 22:00:00 GMT"), at(0)), Some(Duration::ZERO));
+        assert_eq!(retry_after(&headers("soon"), at(0)), None);
+        assert_eq!(retry_after(&HeaderMap::new(), at(0)), None);
+    }
+
+    #[test]
+    fn breaker_opens_half_opens_and_closes() {
+        let p = policy(3);
+        let mut b = Breaker::default();
+        assert!(!b.record(at(0), &p, Some("status 503")));
+        assert!(!b.record(at(1), &p, Some("status 503")));
+        assert_eq!(b.state, BreakerState::Closed);
+        assert_eq!(b.admit(at(1), &p), Ok(()));
+        assert!(b.record(at(2), &p, Some("status 503")));
+        assert_eq!(b.state, BreakerState::Open);
+
+        assert_eq!(b.admit(at(30), &p), Err(at(62)));
+        assert_eq!(b.admit(at(62), &p), Ok(()));
+        assert_eq!(b.state, BreakerState::HalfOpen);
+        // пока пробный идёт, остальные ждут
+        assert_eq!(b.admit(at(63), &p), Err(at(122)));
+
+        // пробный провалился — снова открыт на полный срок
+        assert!(b.record(at(64), &p, Some("timeout")));
+        assert_eq!((b.state, b.open_until), (BreakerState::Open, Some(at(124))));
+        assert_eq!(b.last_error.as_deref(), Some("timeout"));
+
+        assert_eq!(b.admit(at(124), &p), Ok(()));
+        assert!(!b.record(at(125), &p, None));
+        assert_eq!((b.state, b.consecutive_failures, b.open_until), (BreakerState::Closed, 0, None));
+    }
+
+    #[test]
+    fn breaker_disabled_never_opens() {
+        let p = policy(0);
+        let mut b = Breaker::default();
+        for i in 0..100 {
+            assert!(!b.record(at(i), &p, Some("status 500")));
+            assert_eq!(b.admit(at(i), &p), Ok(()));
+        }
+        assert_eq!((b.state, b.consecutive_failures), (BreakerState::Closed, 100));
+    }
+}
@@ -0,0 +1,381 @@
+//! Планировщик фоновых опросов: интервалы и cron с джиттером, история запусков в fetch_runs.
+//! По задаче на источник; при перезагрузке настроек набор задач сверяется с конфигурацией.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/* ---------- Расписание ---------- */
+
+#[derive(Debug, Clone)]
+enum Timing {
+    Every(Duration),
+    Cron(Box<cron::Schedule>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    timing: Timing,
+    jitter: Duration,
+    /// Исходная запись: по ней расписания сравниваются при перезагрузке.
+    spec: String,
+}
+
+impl PartialEq for Schedule {
+    fn eq(&self, other: &Self) -> bool {
+        self.spec == other.spec && self.jitter == other.jitter
+    }
+}
+
+impl Schedule {
+    pub fn every(every: Duration, jitter: Duration) -> Self {
+        Self { timing: Timing::Every(every), jitter, spec: format!("every {}s", every.as_secs()) }
+    }
+
+    /// Cron с секундами (6–7 полей) или классический из 5 полей — тогда в 0 секунд.
+    pub fn cron(expr: &str, jitter: Duration) -> anyhow::Result<Self> {
+        let expr = expr.trim();
+        let full = if expr.split_whitespace().count() == 5 { format!("0 {expr}") } else { expr.to_string() };
+        let parsed = cron::Schedule::from_str(&full)
+            .map_err(|e| anyhow::anyhow!("invalid cron expression '{expr}': {e}"))?;
+        if parsed.upcoming(Utc).next().is_none() {
+            anyhow::bail!("cron expression '{expr}' never fires");
+        }
+        Ok(Self { timing: Timing::Cron(Box::new(parsed)), jitter, spec: format!("cron {expr}") })
+    }
+
+    /// Ближайший плановый срок строго после `t`, без джиттера.
+    fn next_after(&self, t: DateTime<Utc>) -> DateTime<Utc> {
+        match &self.timing {
+            Timing::Every(d) => t + chrono::Duration::from_std(*d).unwrap_or(chrono::Duration::MAX),
+            Timing::Cron(c) => c.after(&t).next().unwrap_or(DateTime::<Utc>::MAX_UTC),
+        }
+    }
+
+    /// Плановый срок следующего запуска: после `last_slot` (после рестарта — последнего успешного
+    /// запуска из fetch_runs), но не раньше `now`; пропущенный срок — сразу.
+    fn next_slot(&self, last_slot: Option<DateTime<Utc>>, now: DateTime<Utc>) -> DateTime<Utc> {
+        last_slot.map_or(now, |t| self.next_after(t)).max(now)
+    }
+
+    fn random_jitter(&self) -> chrono::Duration {
+        let ms = self.jitter.as_millis() as i64;
+        chrono::Duration::milliseconds(if ms > 0 { rand::thread_rng().gen_range(0..=ms) } else { 0 })
+    }
+
+    fn json(&self) -> Value {
+        let mut v = match &self.timing {
+            Timing::Every(d) => serde_json::json!({ "every_seconds": d.as_secs() }),
+            Timing::Cron(_) => serde_json::json!({ "cron": self.spec.trim_start_matches("cron ") }),
+        };
+        v["jitter_seconds"] = self.jitter.as_secs().into();
+        v
+    }
+}
+
+/* ---------- Задачи ---------- */
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct JobState {
+    next_run_at: Option<DateTime<Utc>>,
+    running_since: Option<DateTime<Utc>>,
+}
+
+struct Job {
+    /// `None` — остановиться после текущего опроса.
+    ctl: watch::Sender<Option<Schedule>>,
+    state: Arc<Mutex<JobState>>,
+}
+
+pub struct Jobs {
+    pool: PgPool,
+    tasks: Mutex<HashMap<String, Job>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Reconciled {
+    pub started: Vec<String>,
+    pub stopped: Vec<String>,
+    pub rescheduled: Vec<String>,
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Jobs {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool, tasks: Mutex::default() }
+    }
+
+    /// Приводит набор задач к `desired`. Идущие опросы не прерываются: задача читает
+    /// новое расписание или команду остановки только между опросами.
+    /// `run` возвращает число записанных строк.
+    pub fn reconcile<F, Fut>(&self, desired: BTreeMap<String, Schedule>, run: F) -> Reconciled
+    where
+        F: Fn(String) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<u64>> + Send + 'static,
+    {
+        let mut tasks = lock(&self.tasks);
+        let mut out = Reconciled::default();
+
+        tasks.retain(|name, job| {
+            if desired.contains_key(name) {
+                return true;
+            }
+            let _ = job.ctl.send(None);
+            out.stopped.push(name.clone());
+            false
+        });
+
+        for (name, schedule) in desired {
+            if let Some(job) = tasks.get(&name) {
+                let changed = job.ctl.send_if_modified(|cur| {
+                    let changed = cur.as_ref() != Some(&schedule);
+                    *cur = Some(schedule.clone());
+                    changed
+                });
+                if changed {
+                    out.rescheduled.push(name);
+                }
+                continue;
+            }
+            let (ctl, rx) = watch::channel(Some(schedule));
+            let state = Arc::new(Mutex::new(JobState::default()));
+            tokio::spawn(job_loop(name.clone(), self.pool.clone(), rx, state.clone(), run.clone()));
+            tasks.insert(name.clone(), Job { ctl, state });
+            out.started.push(name);
+        }
+
+        if !(out.started.is_empty() && out.stopped.is_empty() && out.rescheduled.is_empty()) {
+            info!("jobs: started {:?}, stopped {:?}, rescheduled {:?}", out.started, out.stopped, out.rescheduled);
+        }
+        out
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        lock(&self.tasks).contains_key(name)
+    }
+
+    /// Текущие задачи: расписание, ближайший запуск, последний запуск и последний успешный из fetch_runs.
+    pub async fn overview(&self) -> anyhow::Result<Vec<Value>> {
+        let live: Vec<(String, Option<Schedule>, JobState)> = lock(&self.tasks)
+            .iter()
+            .map(|(name, job)| (name.clone(), job.ctl.borrow().clone(), lock(&job.state).clone()))
+            .collect();
+
+        // по одной строке на задачу через ix_fetch_runs_job, без просмотра всей истории
+        let names: Vec<&str> = live.iter().map(|(name, ..)| name.as_str()).collect();
+        let rows = sqlx::query(&format!(
+            "SELECT r.*, s.started_at AS last_success_at
+             FROM unnest($1::text[]) AS j(name)
+             CROSS JOIN LATERAL (
+                 SELECT {RUN_COLUMNS} FROM fetch_runs WHERE job = j.name ORDER BY started_at DESC LIMIT 1
+             ) r
+             LEFT JOIN LATERAL (
+                 SELECT started_at FROM fetch_runs WHERE job = j.name AND status = 'ok' ORDER BY started_at DESC LIMIT 1
+             ) s ON true"
+        )).bind(&names).fetch_all(&self.pool).await?;
+        let mut last: HashMap<String, (Value, Option<DateTime<Utc>>)> = rows.iter()
+            .map(|r| (r.get("job"), (run_json(r), r.get("last_success_at"))))
+            .collect();
+
+        let mut out: Vec<Value> = live.into_iter().map(|(name, schedule, state)| {
+            let (last_run, last_success_at) = last.remove(&name).unzip();
+            serde_json::json!({
+                "name": name,
+                "schedule": schedule.map(|s| s.json()),
+                "next_run_at": state.next_run_at,
+                "running_since": state.running_since,
+                "last_run": last_run,
+                "last_success_at": last_success_at.flatten(),
+            })
+        }).collect();
+        out.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        Ok(out)
+    }
+}
+
+async fn job_loop<F, Fut>(
+    name: String,
+    pool: PgPool,
+    mut ctl: watch::Receiver<Option<Schedule>>,
+    state: Arc<Mutex<JobState>>,
+    run: F,
+) where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = anyhow::Result<u64>>,
+{
+    // после рестарта отсчитываем от последнего успешного запуска, а не от «сейчас»
+    let mut last_slot = match last_success(&pool, &name).await {
+        Ok(t) => t,
+        Err(e) => { warn!("{name}: cannot read last successful run: {e}"); None }
+    };
+
+    loop {
+        // ждём срок; смена расписания пересчитывает его
+        let slot = loop {
+            let Some(schedule) = ctl.borrow_and_update().clone() else { return };
+            let now = Utc::now();
+            let slot = schedule.next_slot(last_slot, now);
+            let fire_at = slot + schedule.random_jitter();
+            lock(&state).next_run_at = Some(fire_at);
+            let wait = (fire_at - now).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => break slot,
+                changed = ctl.changed() => if changed.is_err() { return },
+            }
+        };
+        // следующий срок считаем от планового, а не от фактического: интервалы не плывут
+        last_slot = Some(slot);
+
+        let started_at = Utc::now();
+        *lock(&state) = JobState { next_run_at: None, running_since: Some(started_at) };
+        let run_id = match start_run(&pool, &name, started_at).await {
+            Ok(id) => Some(id),
+            Err(e) => { warn!("{name}: cannot record run: {e}"); None }
+        };
+        let result = run(name.clone()).await;
+        if let Err(e) = &result {
+            error!("{name} err {e:?}");
+        }
+        if let Some(id) = run_id {
+            if let Err(e) = finish_run(&pool, &name, id, &result).await {
+                warn!("{name}: cannot record run result: {e}");
+            }
+        }
+        lock(&state).running_since = None;
+    }
+}
+
+/* ---------- fetch_runs ---------- */
+
+const RUN_COLUMNS: &str = "id, job, started_at, finished_at, status, error, rows_written";
+
+/// Сколько дней хранить историю запусков; последний успешный запуск задачи остаётся всегда —
+/// от него отсчитывается расписание после рестарта.
+const RUNS_RETENTION_DAYS: i32 = 30;
+
+pub fn run_json(r: &sqlx::postgres::PgRow) -> Value {
+    serde_json::json!({
+        "id": r.get::<i64,_>("id"),
+        "job": r.get::<String,_>("job"),
+        "started_at": r.get::<DateTime<Utc>,_>("started_at"),
+        "finished_at": r.get::<Option<DateTime<Utc>>,_>("finished_at"),
+        "status": r.get::<String,_>("status"),
+        "error": r.get::<Option<String>,_>("error"),
+        "rows_written": r.get::<Option<i64>,_>("rows_written"),
+    })
+}
+
+async fn last_success(pool: &PgPool, job: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+    Ok(sqlx::query_scalar(
+        "SELECT started_at FROM fetch_runs WHERE job = $1 AND status = 'ok' ORDER BY started_at DESC LIMIT 1"
+    ).bind(job).fetch_optional(pool).await?)
+}
+
+async fn start_run(pool: &PgPool, job: &str, started_at: DateTime<Utc>) -> anyhow::Result<i64> {
+    Ok(sqlx::query_scalar("INSERT INTO fetch_runs(job, started_at, status) VALUES ($1, $2, 'running') RETURNING id")
+        .bind(job).bind(started_at).fetch_one(pool).await?)
+}
+
+async fn finish_run(pool: &PgPool, job: &str, id: i64, result: &anyhow::Result<u64>) -> anyhow::Result<()> {
+    let (status, error, rows) = match result {
+        Ok(n) => ("ok", None, Some(*n as i64)),
+        Err(e) => ("error", Some(e.to_string()), None),
+    };
+    sqlx::query("UPDATE fetch_runs SET finished_at = now(), status = $2, error = $3, rows_written = $4 WHERE id = $1")
+        .bind(id).bind(status).bind(error).bind(rows).execute(pool).await?;
+    sqlx::query(
+        "DELETE FROM fetch_runs WHERE job = $1 AND started_at < now() - make_interval(days => $2)
+           AND id IS DISTINCT FROM (SELECT id FROM fetch_runs WHERE job = $1 AND status = 'ok'
+                                    ORDER BY started_at DESC LIMIT 1)"
+    ).bind(job).bind(RUNS_RETENTION_DAYS).execute(pool).await?;
+    Ok(())
+}
+
+/// Последние запуски задачи, новые первыми.
+pub async fn runs(pool: &PgPool, job: &str, limit: i64) -> anyhow::Result<Vec<Value>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {RUN_COLUMNS} FROM fetch_runs WHERE job = $1 ORDER BY started_at DESC, id DESC LIMIT $2"
+    )).bind(job).bind(limit).fetch_all(pool).await?;
+    Ok(rows.iter().map(run_json).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn five_field_cron_fires_at_second_zero() {
+        let five = Schedule::cron(" 30 5 * * * ", Duration::ZERO).unwrap();
+        let six = Schedule::cron("0 30 5 * * *", Duration::ZERO).unwrap();
+        let t = utc("2026-03-01T12:00:00Z");
+        assert_eq!(five.next_after(t), utc("2026-03-02T05:30:00Z"));
+        assert_eq!(five.next_after(t), six.next_after(t));
+        assert_eq!(five.json()["cron"], "30 5 * * *");
+        // шестое поле — секунды
+        let secs = Schedule::cron("15 30 5 * * *", Duration::ZERO).unwrap();
+        assert_eq!(secs.next_after(t), utc("2026-03-02T05:30:15Z"));
+    }
+
+    #[test]
+    fn bad_cron_is_rejected() {
+        assert!(Schedule::cron("every day", Duration::ZERO).is_err());
+        assert!(Schedule::cron("* * *", Duration::ZERO).is_err());
+        let never = Schedule::cron("0 0 0 1 1 * 2001", Duration::ZERO).unwrap_err().to_string();
+        assert!(never.contains("never fires"), "{never}");
+    }
+
+    #[test]
+    fn next_run_from_last_success() {
+        let hourly = Schedule::every(Duration::from_secs(3600), Duration::ZERO);
+        let now = utc("2026-03-01T12:00:00Z");
+        // запусков не было — сразу
+        assert_eq!(hourly.next_slot(None, now), now);
+        // после рестарта — через интервал от последнего успешного
+        assert_eq!(hourly.next_slot(Some(utc("2026-03-01T11:20:00Z")), now), utc("2026-03-01T12:20:00Z"));
+        // срок прошёл, пока сервис стоял, — сразу
+        assert_eq!(hourly.next_slot(Some(utc("2026-03-01T09:00:00Z")), now), now);
+
+        let daily = Schedule::cron("30 5 * * *", Duration::ZERO).unwrap();
+        assert_eq!(daily.next_slot(Some(utc("2026-03-01T05:30:00Z")), now), utc("2026-03-02T05:30:00Z"));
+        assert_eq!(daily.next_slot(Some(utc("2026-02-27T05:30:00Z")), now), now);
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let none = Schedule::every(Duration::from_secs(60), Duration::ZERO);
+        assert_eq!(none.random_jitter(), chrono::Duration::zero());
+        let some = Schedule::every(Duration::from_secs(60), Duration::from_secs(5));
+        for _ in 0..200 {
+            let j = some.random_jitter();
+            assert!(j >= chrono::Duration::zero() && j <= chrono::Duration::seconds(5), "{j}");
+        }
+    }
+
+    #[test]
+    fn schedules_compare_by_spec_and_jitter() {
+        let a = Schedule::every(Duration::from_secs(60), Duration::ZERO);
+        assert_eq!(a, Schedule::every(Duration::from_secs(60), Duration::ZERO));
+        assert_ne!(a, Schedule::every(Duration::from_secs(60), Duration::from_secs(1)));
+        assert_ne!(a, Schedule::every(Duration::from_secs(61), Duration::ZERO));
+        assert_eq!(Schedule::cron("0 * * * *", Duration::ZERO).unwrap(), Schedule::cron("0 * * * *", Duration::ZERO).unwrap());
+    }
+}
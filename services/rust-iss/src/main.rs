@@ -1,33 +1,171 @@
-use std::{collections::HashMap, time::Duration};
+mod alerts;
+mod config;
+mod donki;
+mod geo;
+mod http;
+mod jobs;
+mod neo;
+mod passes;
+mod quota;
+mod sgp4;
+mod sources;
+
+use std::{collections::{BTreeMap, HashMap}, convert::Infallible, net::SocketAddr, sync::{Arc, RwLock}, time::Duration};
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    routing::get,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Path, Query, Request, State,
+    },
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
     Json, Router,
 };
 use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::{postgres::PgPoolOptions, PgPool, Row};
-use tracing::{error, info};
+use tokio::sync::{broadcast, Notify, OwnedSemaphorePermit, Semaphore};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+use tracing::{error, info, warn};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
+use geo::{haversine_km, TrackPoint};
+use http::{Breaker, Http, Validators};
+use jobs::{Jobs, Reconciled, Schedule};
+use config::{Env, SourcesConfig};
+use sources::Registry;
+
 #[derive(Serialize)]
-struct Health { status: &'static str, now: DateTime<Utc> }
+struct Health {
+    status: &'static str, // degraded — у какого-то внешнего хоста разомкнут предохранитель
+    now: DateTime<Utc>,
+    upstreams: BTreeMap<String, Breaker>,
+}
 
 #[derive(Clone)]
 struct AppState {
     pool: PgPool,
-    nasa_url: String,          // OSDR
-    nasa_key: String,          // ключ NASA
-    fallback_url: String,      // ISS where-the-iss
-    every_osdr: u64,
-    every_iss: u64,
-    every_apod: u64,
-    every_neo: u64,
-    every_donki: u64,
-    every_spacex: u64,
+    settings: Arc<RwLock<Arc<Settings>>>,      // перечитываются по SIGHUP и POST /admin/reload
+    jobs: Arc<Jobs>,                            // фоновые опросы источников
+    http: Arc<Http>,                            // повторы и предохранители внешних запросов
+    iss_tx: broadcast::Sender<Arc<Value>>,      // новые строки iss_fetch_log для /iss/stream и /ws/iss
+    iss_subscribers: Arc<Semaphore>,            // лимит одновременных подписчиков
+    alerts_wake: Arc<Notify>,                   // будит доставку новых оповещений
+}
+
+/// Настройки из окружения (с .env) и sources.toml. DATABASE_URL и ISS_STREAM_MAX_SUBSCRIBERS
+/// читаются только при старте.
+struct Settings {
+    config: SourcesConfig,     // sources.toml + окружение
+    sources: Registry,         // ленты space_cache: APOD, NeoWs, DONKI, SpaceX
+    nasa_item_url: String,     // OSDR, одна запись; {id} -> accession
+    iss_providers: Vec<IssProvider>, // источники положения МКС по порядку опроса
+    tle_file: Option<String>,  // локальный TLE для офлайна
+    schedule: BTreeMap<String, Schedule>, // включённые фоновые опросы
+    osdr_list_limit: i64,      // limit /osdr/list по умолчанию
+    admin_token: Option<String>, // Bearer-токен /admin/*; без него — только с localhost
+}
+
+impl Settings {
+    fn from_env(env: &Env) -> anyhow::Result<Self> {
+        let config = SourcesConfig::load(env)?;
+        let nasa_item_url = env.raw("NASA_API_ITEM_URL")
+            .unwrap_or("https://visualization.osdr.nasa.gov/biodata/api/v2/dataset/{id}/?format=json").to_string();
+
+        let where_iss_url = env.raw("WHERE_ISS_URL").unwrap_or("https://api.wheretheiss.at/v1/satellites/25544");
+        let open_notify_url = env.raw("OPEN_NOTIFY_URL").unwrap_or("http://api.open-notify.org/iss-now.json");
+        let iss_providers = IssProvider::parse_list(
+            env.raw("ISS_PROVIDERS").unwrap_or("wheretheiss,open-notify,tle"),
+            where_iss_url, open_notify_url,
+        )?;
+
+        let tle_file = env.get("ISS_TLE_FILE").map(str::to_string);
+        let osdr_list_limit = env.get("OSDR_LIST_LIMIT").and_then(|s| s.parse().ok()).unwrap_or(20);
+        let admin_token = env.get("ADMIN_TOKEN").map(str::to_string);
+
+        let sources = Registry::from_config(&config.feeds);
+        let builtin = [("osdr", &config.osdr), ("iss", &config.iss), ("tle", &config.tle)]
+            .into_iter()
+            .filter(|(_, cfg)| cfg.enabled);
+        let feeds = sources.iter().map(|src| (src.name(), src.config()));
+        let schedule = builtin.chain(feeds)
+            .map(|(name, cfg)| Ok((name.to_string(), cfg.schedule()?)))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { config, sources, nasa_item_url, iss_providers, tle_file, schedule, osdr_list_limit, admin_token })
+    }
+}
+
+impl AppState {
+    fn settings(&self) -> Arc<Settings> {
+        self.settings.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Перечитывает .env и настройки и сверяет с ними фоновые задачи.
+    /// При ошибке в настройках продолжаем работать со старыми.
+    fn reload(&self) -> anyhow::Result<Reconciled> {
+        let settings = Arc::new(Settings::from_env(&Env::load())?);
+        self.http.configure(settings.config.http.clone(), settings.config.nasa.clone())?;
+        *self.settings.write().unwrap_or_else(|e| e.into_inner()) = settings.clone();
+        Ok(self.reconcile_jobs(&settings))
+    }
+
+    fn reconcile_jobs(&self, settings: &Settings) -> Reconciled {
+        let st = self.clone();
+        self.jobs.reconcile(settings.schedule.clone(), move |name| {
+            let st = st.clone();
+            async move { run_job(&st, &name).await }
+        })
+    }
+}
+
+/// Один опрос источника по имени из `Settings::schedule`; возвращает число записанных строк.
+async fn run_job(st: &AppState, name: &str) -> anyhow::Result<u64> {
+    match name {
+        "osdr" => fetch_and_store_osdr(st).await.map(|s| s.written as u64),
+        "iss" => fetch_and_store_iss(st).await.map(|()| 1),
+        "tle" => fetch_and_store_tle(st).await,
+        feed => match st.settings().sources.get(feed) {
+            Some(feed) => run_feed(st, &feed).await,
+            None => Ok(0), // выключили, пока задача ждала
+        },
+    }
+}
+
+/// Опрос ленты space_cache и проверка правил оповещений по свежим данным.
+/// Ошибка оповещений опрос не проваливает.
+async fn run_feed(st: &AppState, src: &sources::Feed) -> anyhow::Result<u64> {
+    let written = sources::run(src, &st.http, &st.pool).await?;
+    match alerts::evaluate(&st.pool, &st.settings().config.alerts, src.name()).await {
+        Ok(0) => {}
+        Ok(_) => st.alerts_wake.notify_one(),
+        Err(e) => warn!("alerts for {} failed: {e:#}", src.name()),
+    }
+    Ok(written)
+}
+
+/// Доставка оповещений на вебхуки: сразу после новых и раз в полминуты для повторов.
+async fn deliver_alerts(st: AppState) {
+    loop {
+        let settings = st.settings();
+        if let Err(e) = alerts::deliver_due(&st.pool, &st.http.client(), &settings.config.alerts).await {
+            warn!("alert delivery failed: {e:#}");
+        }
+        drop(settings);
+        tokio::select! {
+            _ = st.alerts_wake.notified() => {}
+            _ = tokio::time::sleep(Duration::from_secs(30)) => {}
+        }
+    }
 }
 
 #[tokio::main]
@@ -37,123 +175,99 @@ async fn main() -> anyhow::Result<()> {
         .finish();
     let _ = tracing::subscriber::set_global_default(subscriber);
 
-    dotenvy::dotenv().ok();
-
-    let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is required");
+    let env = Env::load();
+    let db_url = env.get("DATABASE_URL").expect("DATABASE_URL is required").to_string();
 
-    let nasa_url = std::env::var("NASA_API_URL")
-        .unwrap_or_else(|_| "https://visualization.osdr.nasa.gov/biodata/api/v2/datasets/?format=json".to_string());
-    let nasa_key = std::env::var("NASA_API_KEY").unwrap_or_default();
+    let settings = Settings::from_env(&env)?;
 
-    let fallback_url = std::env::var("WHERE_ISS_URL")
-        .unwrap_or_else(|_| "https://api.wheretheiss.at/v1/satellites/25544".to_string());
-
-    let every_osdr   = env_u64("FETCH_EVERY_SECONDS", 600);
-    let every_iss    = env_u64("ISS_EVERY_SECONDS",   120);
-    let every_apod   = env_u64("APOD_EVERY_SECONDS",  43200); // 12ч
-    let every_neo    = env_u64("NEO_EVERY_SECONDS",   7200);  // 2ч
-    let every_donki  = env_u64("DONKI_EVERY_SECONDS", 3600);  // 1ч
-    let every_spacex = env_u64("SPACEX_EVERY_SECONDS",3600);
+    let max_subscribers = env.get("ISS_STREAM_MAX_SUBSCRIBERS").and_then(|s| s.parse().ok()).unwrap_or(100);
+    let (iss_tx, _) = broadcast::channel(ISS_STREAM_BUFFER);
 
     let pool = PgPoolOptions::new().max_connections(5).connect(&db_url).await?;
     init_db(&pool).await?;
 
+    let http = Http::new(pool.clone(), settings.config.http.clone(), settings.config.nasa.clone())?;
     let state = AppState {
         pool: pool.clone(),
-        nasa_url: nasa_url.clone(),
-        nasa_key,
-        fallback_url: fallback_url.clone(),
-        every_osdr, every_iss, every_apod, every_neo, every_donki, every_spacex,
+        settings: Arc::new(RwLock::new(Arc::new(settings))),
+        jobs: Arc::new(Jobs::new(pool.clone())),
+        http: Arc::new(http),
+        iss_tx,
+        iss_subscribers: Arc::new(Semaphore::new(max_subscribers)),
+        alerts_wake: Arc::new(Notify::new()),
     };
 
-    // фон OSDR
-    {
-        let st = state.clone();
-        tokio::spawn(async move {
-            loop {
-                if let Err(e) = fetch_and_store_osdr(&st).await { error!("osdr err {e:?}") }
-                tokio::time::sleep(Duration::from_secs(st.every_osdr)).await;
-            }
-        });
-    }
-    // фон ISS
-    {
-        let st = state.clone();
-        tokio::spawn(async move {
-            loop {
-                if let Err(e) = fetch_and_store_iss(&st.pool, &st.fallback_url).await { error!("iss err {e:?}") }
-                tokio::time::sleep(Duration::from_secs(st.every_iss)).await;
-            }
-        });
-    }
-    // фон APOD
-    {
-        let st = state.clone();
-        tokio::spawn(async move {
-            loop {
-                if let Err(e) = fetch_apod(&st).await { error!("apod err {e:?}") }
-                tokio::time::sleep(Duration::from_secs(st.every_apod)).await;
-            }
-        });
-    }
-    // фон NeoWs
-    {
-        let st = state.clone();
-        tokio::spawn(async move {
-            loop {
-                if let Err(e) = fetch_neo_feed(&st).await { error!("neo err {e:?}") }
-                tokio::time::sleep(Duration::from_secs(st.every_neo)).await;
-            }
-        });
-    }
-    // фон DONKI
-    {
-        let st = state.clone();
-        tokio::spawn(async move {
-            loop {
-                if let Err(e) = fetch_donki(&st).await { error!("donki err {e:?}") }
-                tokio::time::sleep(Duration::from_secs(st.every_donki)).await;
-            }
-        });
-    }
-    // фон SpaceX
+    // фон: OSDR, ISS, TLE и ленты space_cache
+    state.reconcile_jobs(&state.settings());
+    tokio::spawn(deliver_alerts(state.clone()));
+
+    // SIGHUP — то же, что POST /admin/reload
+    #[cfg(unix)]
     {
         let st = state.clone();
+        let mut hup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
         tokio::spawn(async move {
-            loop {
-                if let Err(e) = fetch_spacex_next(&st).await { error!("spacex err {e:?}") }
-                tokio::time::sleep(Duration::from_secs(st.every_spacex)).await;
+            while hup.recv().await.is_some() {
+                match st.reload() {
+                    Ok(r) => info!("reloaded on SIGHUP: {r:?}"),
+                    Err(e) => error!("reload on SIGHUP failed, keeping previous settings: {e:#}"),
+                }
             }
         });
     }
 
     let app = Router::new()
         // общее
-        .route("/health", get(|| async { Json(Health { status: "ok", now: Utc::now() }) }))
+        .route("/health", get(health))
         .with_state(state.clone())
         // ISS
         .route("/last", get(last_iss))
         .route("/fetch", get(trigger_iss))
         .route("/iss/trend", get(iss_trend))
+        .route("/iss/track", get(iss_track))
+        .route("/iss/track.geojson", get(iss_track_geojson))
+        .route("/iss/track.kml", get(iss_track_kml))
+        .route("/iss/predict", get(iss_predict))
+        .route("/iss/predict/track", get(iss_predict_track))
+        .route("/iss/passes", get(iss_passes))
+        .route("/iss/stream", get(iss_stream))
+        .route("/ws/iss", get(ws_iss))
         // OSDR
         .route("/osdr/sync", get(osdr_sync))
         .route("/osdr/list", get(osdr_list))
+        .route("/osdr/search", get(osdr_search))
+        .route("/osdr/:dataset_id", get(osdr_item))
+        .route("/osdr/:dataset_id/history", get(osdr_history))
+        // NeoWs
+        .route("/neo/approaches", get(neo_approaches))
+        .route("/neo/:id", get(neo_object))
+        // DONKI
+        .route("/donki/events", get(donki_events))
+        .route("/donki/events/:id", get(donki_event))
+        .route("/donki/flares/stats", get(donki_flare_stats))
+        .route("/donki/notifications", get(donki_notifications))
+        // оповещения
+        .route("/alerts", get(alerts_history))
         // Space cache
         .route("/space/:src/latest", get(space_latest))
         .route("/space/refresh", get(space_refresh))
         .route("/space/summary", get(space_summary))
+        // фоновые опросы
+        .route("/jobs", get(jobs_list))
+        .route("/jobs/:name/runs", get(job_runs))
+        // администрирование: только с ADMIN_TOKEN или localhost
+        .merge(Router::new()
+            .route("/admin/reload", post(admin_reload))
+            .route("/admin/quota", get(admin_quota))
+            .route_layer(middleware::from_fn_with_state(state.clone(), require_admin)))
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(("0.0.0.0", 3000)).await?;
     info!("rust_iss listening on 0.0.0.0:3000");
-    axum::serve(listener, app.into_make_service()).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
     Ok(())
 }
 
-fn env_u64(k: &str, d: u64) -> u64 {
-    std::env::var(k).ok().and_then(|s| s.parse().ok()).unwrap_or(d)
-}
-
 /* ---------- DB boot ---------- */
 async fn init_db(pool: &PgPool) -> anyhow::Result<()> {
     // ISS
@@ -165,6 +279,29 @@ async fn init_db(pool: &PgPool) -> anyhow::Result<()> {
             payload JSONB NOT NULL
         )"
     ).execute(pool).await?;
+    sqlx::query(
+        "ALTER TABLE iss_fetch_log
+            ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION,
+            ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION,
+            ADD COLUMN IF NOT EXISTS altitude_km DOUBLE PRECISION,
+            ADD COLUMN IF NOT EXISTS velocity_kmh DOUBLE PRECISION,
+            ADD COLUMN IF NOT EXISTS visibility TEXT,
+            ADD COLUMN IF NOT EXISTS footprint_km DOUBLE PRECISION,
+            ADD COLUMN IF NOT EXISTS position_at TIMESTAMPTZ,
+            ADD COLUMN IF NOT EXISTS parsed_at TIMESTAMPTZ"
+    ).execute(pool).await?;
+    // очередь разбора: после первого прохода в индексе только то, что ещё не разбиралось
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_iss_fetch_log_unparsed ON iss_fetch_log(id) WHERE parsed_at IS NULL")
+        .execute(pool).await?;
+    backfill_iss_positions(pool).await?;
+    // retention_days: удаление старых строк после каждого опроса
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_iss_fetch_log_fetched_at ON iss_fetch_log(fetched_at)")
+        .execute(pool).await?;
+    // трек и тренд отбирают и сортируют по времени положения
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS ix_iss_fetch_log_position_time
+         ON iss_fetch_log((COALESCE(position_at, fetched_at)))"
+    ).execute(pool).await?;
 
     // OSDR
     sqlx::query(
@@ -182,6 +319,41 @@ async fn init_db(pool: &PgPool) -> anyhow::Result<()> {
         "CREATE UNIQUE INDEX IF NOT EXISTS ux_osdr_dataset_id
          ON osdr_items(dataset_id) WHERE dataset_id IS NOT NULL"
     ).execute(pool).await?;
+    // полнотекстовый поиск: title (A), описание (B), организм/тип анализа (C)
+    sqlx::query(&format!(
+        "ALTER TABLE osdr_items ADD COLUMN IF NOT EXISTS search_tsv tsvector
+         GENERATED ALWAYS AS (
+             setweight(to_tsvector('english'::regconfig, COALESCE(title, '')), 'A') ||
+             setweight(to_tsvector('english'::regconfig, {OSDR_TEXT_DESCRIPTION}), 'B') ||
+             setweight(to_tsvector('english'::regconfig, {OSDR_TEXT_DETAILS}), 'C')
+         ) STORED"
+    )).execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_osdr_search_tsv ON osdr_items USING GIN(search_tsv)").execute(pool).await?;
+
+    // история ревизий raw по dataset_id
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS osdr_item_versions(
+            id BIGSERIAL PRIMARY KEY,
+            dataset_id TEXT NOT NULL,
+            version INT NOT NULL,
+            content_hash TEXT NOT NULL,
+            title TEXT,
+            status TEXT,
+            updated_at TIMESTAMPTZ,
+            recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            raw JSONB NOT NULL,
+            UNIQUE(dataset_id, version)
+        )"
+    ).execute(pool).await?;
+    // первая версия для строк, появившихся до истории
+    sqlx::query(
+        "INSERT INTO osdr_item_versions(dataset_id, version, content_hash, title, status, updated_at, recorded_at, raw)
+         SELECT i.dataset_id, 1, encode(sha256(convert_to(i.raw::text, 'UTF8')), 'hex'),
+                i.title, i.status, i.updated_at, i.inserted_at, i.raw
+         FROM osdr_items i
+         WHERE i.dataset_id IS NOT NULL
+           AND NOT EXISTS (SELECT 1 FROM osdr_item_versions v WHERE v.dataset_id = i.dataset_id)"
+    ).execute(pool).await?;
 
     // универсальный кэш космоданных
     sqlx::query(
@@ -194,38 +366,334 @@ async fn init_db(pool: &PgPool) -> anyhow::Result<()> {
     ).execute(pool).await?;
     sqlx::query("CREATE INDEX IF NOT EXISTS ix_space_cache_source ON space_cache(source,fetched_at DESC)").execute(pool).await?;
 
+    // TLE МКС для SGP4
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS iss_tle(
+            id BIGSERIAL PRIMARY KEY,
+            fetched_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            source TEXT NOT NULL,
+            name TEXT,
+            norad_id INT NOT NULL,
+            epoch TIMESTAMPTZ NOT NULL,
+            line1 TEXT NOT NULL,
+            line2 TEXT NOT NULL,
+            UNIQUE(line1, line2)
+        )"
+    ).execute(pool).await?;
+
+    // история фоновых опросов
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS fetch_runs(
+            id BIGSERIAL PRIMARY KEY,
+            job TEXT NOT NULL,
+            started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            finished_at TIMESTAMPTZ,
+            status TEXT NOT NULL,          -- running | ok | error | interrupted
+            error TEXT,
+            rows_written BIGINT
+        )"
+    ).execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_fetch_runs_job ON fetch_runs(job, started_at DESC)").execute(pool).await?;
+    // запуски, оборванные прошлым процессом
+    sqlx::query("UPDATE fetch_runs SET status = 'interrupted', finished_at = now() WHERE status = 'running'")
+        .execute(pool).await?;
+
+    // NeoWs: объекты и сближения
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS neo_objects(
+            id TEXT PRIMARY KEY,           -- neo_reference_id
+            name TEXT NOT NULL,
+            nasa_jpl_url TEXT,
+            absolute_magnitude_h DOUBLE PRECISION,
+            diameter_min_km DOUBLE PRECISION,
+            diameter_max_km DOUBLE PRECISION,
+            hazardous BOOLEAN NOT NULL,
+            sentry BOOLEAN NOT NULL DEFAULT FALSE,
+            first_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )"
+    ).execute(pool).await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS neo_close_approaches(
+            id BIGSERIAL PRIMARY KEY,
+            neo_id TEXT NOT NULL REFERENCES neo_objects(id) ON DELETE CASCADE,
+            approach_at TIMESTAMPTZ NOT NULL,
+            orbiting_body TEXT NOT NULL,
+            miss_km DOUBLE PRECISION,
+            miss_lunar DOUBLE PRECISION,
+            miss_au DOUBLE PRECISION,
+            velocity_kps DOUBLE PRECISION,
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            UNIQUE (neo_id, approach_at, orbiting_body)
+        )"
+    ).execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_neo_close_approaches_at ON neo_close_approaches(approach_at)")
+        .execute(pool).await?;
+
+    // DONKI: события по их идентификаторам и связи между ними (linkedEvents)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS donki_events(
+            id TEXT PRIMARY KEY,           -- activityID / flrID / gstID ...
+            event_type TEXT NOT NULL,      -- FLR | CME | GST | ...
+            start_at TIMESTAMPTZ,
+            peak_at TIMESTAMPTZ,
+            end_at TIMESTAMPTZ,
+            class_type TEXT,
+            source_location TEXT,
+            active_region INTEGER,
+            speed_kms DOUBLE PRECISION,
+            link TEXT,
+            payload JSONB NOT NULL,
+            first_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )"
+    ).execute(pool).await?;
+    sqlx::query("ALTER TABLE donki_events ADD COLUMN IF NOT EXISTS kp_max DOUBLE PRECISION").execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_donki_events_type_start ON donki_events(event_type, start_at)")
+        .execute(pool).await?;
+    // связанное событие может ещё не быть в базе — внешнего ключа на to_id нет
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS donki_links(
+            from_id TEXT NOT NULL REFERENCES donki_events(id) ON DELETE CASCADE,
+            to_id TEXT NOT NULL,
+            PRIMARY KEY (from_id, to_id)
+        )"
+    ).execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_donki_links_to ON donki_links(to_id)").execute(pool).await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS donki_notifications(
+            id TEXT PRIMARY KEY,           -- messageID
+            message_type TEXT NOT NULL,
+            issued_at TIMESTAMPTZ,
+            url TEXT,
+            body TEXT,
+            fetched_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )"
+    ).execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_donki_notifications_issued ON donki_notifications(issued_at)")
+        .execute(pool).await?;
+
+    // история оповещений; (rule, event_key) — одно событие срабатывает по правилу один раз
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS alerts(
+            id BIGSERIAL PRIMARY KEY,
+            rule TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            event_key TEXT NOT NULL,
+            event_at TIMESTAMPTZ,
+            summary TEXT NOT NULL,
+            details JSONB NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            UNIQUE (rule, event_key)
+        )"
+    ).execute(pool).await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS alert_deliveries(
+            id BIGSERIAL PRIMARY KEY,
+            alert_id BIGINT NOT NULL REFERENCES alerts(id) ON DELETE CASCADE,
+            webhook TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending', -- pending | delivered | failed
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            last_error TEXT,
+            delivered_at TIMESTAMPTZ
+        )"
+    ).execute(pool).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS ix_alert_deliveries_due ON alert_deliveries(next_attempt_at) WHERE status = 'pending'")
+        .execute(pool).await?;
+
+    // ETag/Last-Modified последних ответов; URL без api_key
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS http_validators(
+            url TEXT PRIMARY KEY,
+            etag TEXT,
+            last_modified TEXT,
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )"
+    ).execute(pool).await?;
+
     Ok(())
 }
 
 /* ---------- ISS ---------- */
+
+/// Положение МКС в разобранном виде (ответ wheretheiss.at).
+#[derive(Debug, Clone, Serialize)]
+struct IssPosition {
+    latitude: f64,
+    longitude: f64,
+    altitude_km: Option<f64>,
+    velocity_kmh: Option<f64>,
+    visibility: Option<String>,
+    footprint_km: Option<f64>,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl IssPosition {
+    /// `None`, если в ответе нет координат.
+    fn from_payload(v: &Value) -> Option<Self> {
+        Some(Self {
+            latitude: num(&v["latitude"])?,
+            longitude: num(&v["longitude"])?,
+            altitude_km: num(&v["altitude"]),
+            velocity_kmh: num(&v["velocity"]),
+            visibility: s_pick(v, &["visibility"]),
+            footprint_km: num(&v["footprint"]),
+            timestamp: t_pick(v, &["timestamp"]),
+        })
+    }
+
+    fn from_row(r: &sqlx::postgres::PgRow) -> Option<Self> {
+        Some(Self {
+            latitude: r.get::<Option<f64>,_>("latitude")?,
+            longitude: r.get::<Option<f64>,_>("longitude")?,
+            altitude_km: r.get("altitude_km"),
+            velocity_kmh: r.get("velocity_kmh"),
+            visibility: r.get("visibility"),
+            footprint_km: r.get("footprint_km"),
+            timestamp: r.get("position_at"),
+        })
+    }
+}
+
+/// Разбирает payload строк, записанных до появления типизированных колонок. Каждая строка
+/// разбирается один раз: нераспознанные тоже получают parsed_at и при следующем старте не читаются.
+async fn backfill_iss_positions(pool: &PgPool) -> anyhow::Result<()> {
+    // строки, разобранные до появления parsed_at
+    sqlx::query("UPDATE iss_fetch_log SET parsed_at = fetched_at WHERE parsed_at IS NULL AND latitude IS NOT NULL")
+        .execute(pool).await?;
+    let (mut filled, mut failed) = (0usize, 0usize);
+    loop {
+        let rows = sqlx::query(
+            "SELECT id, payload FROM iss_fetch_log
+             WHERE parsed_at IS NULL
+             ORDER BY id LIMIT 1000"
+        ).fetch_all(pool).await?;
+        if rows.is_empty() { break }
+        let mut tx = pool.begin().await?;
+        for r in &rows {
+            let id: i64 = r.get("id");
+            let Some(pos) = IssPosition::from_payload(&r.get::<Value,_>("payload")) else {
+                sqlx::query("UPDATE iss_fetch_log SET parsed_at = now() WHERE id = $1")
+                    .bind(id).execute(&mut *tx).await?;
+                failed += 1;
+                continue;
+            };
+            sqlx::query(
+                "UPDATE iss_fetch_log
+                 SET latitude=$2, longitude=$3, altitude_km=$4, velocity_kmh=$5,
+                     visibility=$6, footprint_km=$7, position_at=$8, parsed_at=now()
+                 WHERE id=$1"
+            ).bind(id)
+             .bind(pos.latitude).bind(pos.longitude).bind(pos.altitude_km).bind(pos.velocity_kmh)
+             .bind(pos.visibility).bind(pos.footprint_km).bind(pos.timestamp)
+             .execute(&mut *tx).await?;
+            filled += 1;
+        }
+        tx.commit().await?;
+    }
+    if filled + failed > 0 { info!("iss backfill: {filled} rows parsed, {failed} without coordinates"); }
+    Ok(())
+}
+
+const ISS_COLUMNS: &str =
+    "id, fetched_at, source_url, payload,
+     latitude, longitude, altitude_km, velocity_kmh, visibility, footprint_km, position_at";
+
 async fn last_iss(State(st): State<AppState>)
 -> Result<Json<Value>, (StatusCode, String)> {
-    let row_opt = sqlx::query(
-        "SELECT id, fetched_at, source_url, payload
+    let row_opt = sqlx::query(&format!(
+        "SELECT {ISS_COLUMNS}
          FROM iss_fetch_log
          ORDER BY id DESC LIMIT 1"
-    ).fetch_optional(&st.pool).await
+    )).fetch_optional(&st.pool).await
      .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     if let Some(row) = row_opt {
-        let id: i64 = row.get("id");
-        let fetched_at: DateTime<Utc> = row.get::<DateTime<Utc>, _>("fetched_at");
-        let source_url: String = row.get("source_url");
-        let payload: Value = row.try_get("payload").unwrap_or(serde_json::json!({}));
-        return Ok(Json(serde_json::json!({
-            "id": id, "fetched_at": fetched_at, "source_url": source_url, "payload": payload
-        })));
+        return Ok(Json(iss_row_json(&row)));
     }
     Ok(Json(serde_json::json!({"message":"no data"})))
 }
 
+/// Строка iss_fetch_log в том виде, в каком её отдают /last и потоки.
+fn iss_row_json(row: &sqlx::postgres::PgRow) -> Value {
+    let id: i64 = row.get("id");
+    let fetched_at: DateTime<Utc> = row.get::<DateTime<Utc>, _>("fetched_at");
+    let source_url: String = row.get("source_url");
+    let payload: Value = row.try_get("payload").unwrap_or(serde_json::json!({}));
+    let position = IssPosition::from_row(row);
+    serde_json::json!({
+        "id": id, "fetched_at": fetched_at, "source_url": source_url,
+        "position": position, "payload": payload
+    })
+}
+
 async fn trigger_iss(State(st): State<AppState>)
 -> Result<Json<Value>, (StatusCode, String)> {
-    fetch_and_store_iss(&st.pool, &st.fallback_url).await
+    fetch_and_store_iss(&st).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     last_iss(State(st)).await
 }
 
+/* ---------- ISS: живой поток ---------- */
+
+/// Сколько сообщений держит broadcast-канал; отставший подписчик получает событие `lagged`.
+const ISS_STREAM_BUFFER: usize = 64;
+const ISS_STREAM_HEARTBEAT: Duration = Duration::from_secs(15);
+/// Клиент WebSocket, не принявший сообщение за это время, отключается.
+const ISS_WS_SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn acquire_subscriber(st: &AppState) -> Result<OwnedSemaphorePermit, (StatusCode, String)> {
+    st.iss_subscribers.clone().try_acquire_owned()
+        .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "too many stream subscribers".to_string()))
+}
+
+async fn iss_stream(State(st): State<AppState>)
+-> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let permit = acquire_subscriber(&st)?;
+    let rx = st.iss_tx.subscribe();
+    let stream = BroadcastStream::new(rx).map(move |msg| {
+        // разрешение живёт, пока жив поток
+        let _permit = &permit;
+        Ok(match msg {
+            Ok(row) => Event::default().event("position").data(row.to_string()),
+            Err(BroadcastStreamRecvError::Lagged(n)) => Event::default().event("lagged").data(n.to_string()),
+        })
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(ISS_STREAM_HEARTBEAT)))
+}
+
+async fn ws_iss(ws: WebSocketUpgrade, State(st): State<AppState>)
+-> Result<impl IntoResponse, (StatusCode, String)> {
+    let permit = acquire_subscriber(&st)?;
+    let rx = st.iss_tx.subscribe();
+    Ok(ws.on_upgrade(move |socket| ws_iss_loop(socket, rx, permit)))
+}
+
+async fn ws_iss_loop(mut socket: WebSocket, mut rx: broadcast::Receiver<Arc<Value>>, _permit: OwnedSemaphorePermit) {
+    let mut heartbeat = tokio::time::interval(ISS_STREAM_HEARTBEAT);
+    heartbeat.tick().await;
+    loop {
+        let out = tokio::select! {
+            msg = rx.recv() => match msg {
+                Ok(row) => Message::Text(serde_json::json!({ "event": "position", "data": &*row }).to_string()),
+                Err(broadcast::error::RecvError::Lagged(n)) =>
+                    Message::Text(serde_json::json!({ "event": "lagged", "skipped": n }).to_string()),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = heartbeat.tick() => Message::Ping(Vec::new()),
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        match tokio::time::timeout(ISS_WS_SEND_TIMEOUT, socket.send(out)).await {
+            Ok(Ok(())) => {}
+            _ => break,
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct Trend {
     movement: bool,
@@ -238,239 +706,1133 @@ struct Trend {
     from_lon: Option<f64>,
     to_lat: Option<f64>,
     to_lon: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<TrendStats>,
+}
+
+#[derive(Serialize)]
+struct TrendStats {
+    window: String,
+    samples: usize,
+    span_sec: f64,
+    distance_km: f64,
+    speed_kmh: MinMaxMean,
+    altitude: Option<AltitudeTrend>,
+    heading_deg: Option<f64>,
+    gaps: Vec<SampleGap>,
+    velocity_check: Option<VelocityCheck>,
+}
+
+#[derive(Serialize)]
+struct MinMaxMean { min: Option<f64>, max: Option<f64>, mean: Option<f64> }
+
+#[derive(Serialize)]
+struct AltitudeTrend { first_km: f64, last_km: f64, delta_km: f64, trend: &'static str }
+
+#[derive(Serialize)]
+struct SampleGap { from: DateTime<Utc>, to: DateTime<Utc>, dt_sec: f64 }
+
+/// Сравнение скорости, выведенной из координат, со скоростью из ответа API. API отдаёт
+/// орбитальную (инерциальную) скорость, поэтому выведенная считается так же: к смещению по
+/// долготе добавляется поворот Земли за интервал, дуга пересчитывается на радиус орбиты.
+/// Без этого наземная скорость расходилась бы с орбитальной на ±1670·cos(широты) км/ч.
+#[derive(Serialize)]
+struct VelocityCheck { derived_mean_kmh: f64, reported_mean_kmh: f64, deviation_pct: f64, consistent: bool }
+
+/// Окно /iss/trend: последние N замеров или интервал времени ("90s", "30m", "6h", "2d").
+enum TrendWindow { Samples(i64), Span(chrono::Duration) }
+
+impl TrendWindow {
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if !s.is_ascii() {
+            return None;
+        }
+        if let Ok(n) = s.parse::<i64>() {
+            return (2..=TREND_MAX_SAMPLES).contains(&n).then_some(Self::Samples(n));
+        }
+        let (i, _) = s.char_indices().last()?;
+        let (n, unit) = s.split_at(i);
+        let n: i64 = n.parse().ok().filter(|n| *n > 0)?;
+        let d = match unit {
+            "s" => chrono::Duration::try_seconds(n),
+            "m" => chrono::Duration::try_minutes(n),
+            "h" => chrono::Duration::try_hours(n),
+            "d" => chrono::Duration::try_days(n),
+            _ => None,
+        }?;
+        (d <= chrono::Duration::days(TREND_MAX_DAYS)).then_some(Self::Span(d))
+    }
 }
 
-async fn iss_trend(State(st): State<AppState>)
+const TREND_MAX_SAMPLES: i64 = 10_000;
+const TREND_MAX_DAYS: i64 = 7;
+/// Интервал считается пропуском, если он длиннее медианного во столько раз.
+const TREND_GAP_FACTOR: f64 = 2.5;
+/// Допустимое расхождение выведенной и заявленной скорости, %.
+const TREND_VELOCITY_TOLERANCE_PCT: f64 = 10.0;
+/// Изменение высоты, которое ещё считается «без изменений», км.
+const TREND_ALTITUDE_STEADY_KM: f64 = 0.05;
+
+#[derive(Deserialize)]
+struct TrendQuery {
+    window: Option<String>,
+    min_move_km: Option<f64>,
+}
+
+async fn iss_trend(Query(q): Query<TrendQuery>, State(st): State<AppState>)
 -> Result<Json<Trend>, (StatusCode, String)> {
-    let rows = sqlx::query("SELECT fetched_at, payload FROM iss_fetch_log ORDER BY id DESC LIMIT 2")
-        .fetch_all(&st.pool).await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let window_name = q.window.clone().unwrap_or_else(|| "2".to_string());
+    let window = TrendWindow::parse(&window_name).ok_or_else(|| (StatusCode::BAD_REQUEST, format!(
+        "window must be a sample count within 2..={TREND_MAX_SAMPLES} or a span like 30m/6h/2d up to {TREND_MAX_DAYS}d"
+    )))?;
+    let min_move_km = q.min_move_km.unwrap_or(0.1);
 
-    if rows.len() < 2 {
+    let rows = match window {
+        TrendWindow::Samples(n) => sqlx::query(
+            "SELECT * FROM (
+                 SELECT id, COALESCE(position_at, fetched_at) AS t, latitude, longitude, altitude_km, velocity_kmh
+                 FROM iss_fetch_log WHERE latitude IS NOT NULL
+                 ORDER BY id DESC LIMIT $1
+             ) w ORDER BY t"
+        ).bind(n).fetch_all(&st.pool).await,
+        TrendWindow::Span(d) => sqlx::query(
+            "SELECT id, COALESCE(position_at, fetched_at) AS t, latitude, longitude, altitude_km, velocity_kmh
+             FROM iss_fetch_log
+             WHERE latitude IS NOT NULL AND COALESCE(position_at, fetched_at) >= $1
+             ORDER BY t"
+        ).bind(Utc::now() - d).fetch_all(&st.pool).await,
+    }.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let pts: Vec<TrackPoint> = rows.iter().map(|r| TrackPoint {
+        t: r.get("t"),
+        lat: r.get("latitude"),
+        lon: r.get("longitude"),
+        altitude_km: r.get("altitude_km"),
+        velocity_kmh: r.get("velocity_kmh"),
+    }).collect();
+
+    let [.., p1, p2] = pts.as_slice() else {
         return Ok(Json(Trend {
             movement: false, delta_km: 0.0, dt_sec: 0.0, velocity_kmh: None,
             from_time: None, to_time: None,
-            from_lat: None, from_lon: None, to_lat: None, to_lon: None
+            from_lat: None, from_lon: None, to_lat: None, to_lon: None,
+            stats: None,
         }));
-    }
-
-    let t2: DateTime<Utc> = rows[0].get("fetched_at");
-    let t1: DateTime<Utc> = rows[1].get("fetched_at");
-    let p2: Value = rows[0].get("payload");
-    let p1: Value = rows[1].get("payload");
-
-    let lat1 = num(&p1["latitude"]);
-    let lon1 = num(&p1["longitude"]);
-    let lat2 = num(&p2["latitude"]);
-    let lon2 = num(&p2["longitude"]);
-    let v2 = num(&p2["velocity"]);
+    };
 
-    let mut delta_km = 0.0;
-    let mut movement = false;
-    if let (Some(a1), Some(o1), Some(a2), Some(o2)) = (lat1, lon1, lat2, lon2) {
-        delta_km = haversine_km(a1, o1, a2, o2);
-        movement = delta_km > 0.1;
-    }
-    let dt_sec = (t2 - t1).num_milliseconds() as f64 / 1000.0;
+    let delta_km = haversine_km(p1.lat, p1.lon, p2.lat, p2.lon);
+    let dt_sec = (p2.t - p1.t).num_milliseconds() as f64 / 1000.0;
 
     Ok(Json(Trend {
-        movement,
+        movement: delta_km > min_move_km,
         delta_km,
         dt_sec,
-        velocity_kmh: v2,
-        from_time: Some(t1),
-        to_time: Some(t2),
-        from_lat: lat1, from_lon: lon1, to_lat: lat2, to_lon: lon2,
+        velocity_kmh: p2.velocity_kmh,
+        from_time: Some(p1.t),
+        to_time: Some(p2.t),
+        from_lat: Some(p1.lat), from_lon: Some(p1.lon),
+        to_lat: Some(p2.lat), to_lon: Some(p2.lon),
+        stats: Some(trend_stats(window_name, &pts)),
     }))
 }
 
-fn num(v: &Value) -> Option<f64> {
-    if let Some(x) = v.as_f64() { return Some(x); }
-    if let Some(s) = v.as_str() { return s.parse::<f64>().ok(); }
-    None
+fn trend_stats(window: String, pts: &[TrackPoint]) -> TrendStats {
+    let mut distance_km = 0.0;
+    let mut speeds = Vec::new();
+    let mut orbital_speeds = Vec::new();
+    let mut dts = Vec::new();
+    for w in pts.windows(2) {
+        let (a, b) = (&w[0], &w[1]);
+        let dt = (b.t - a.t).num_milliseconds() as f64 / 1000.0;
+        let d = haversine_km(a.lat, a.lon, b.lat, b.lon);
+        distance_km += d;
+        if dt <= 0.0 { continue; }
+        dts.push(dt);
+        speeds.push(d / dt * 3600.0);
+        // орбитальная скорость: дуга в инерциальной системе (Земля под трассой повернулась
+        // на восток), пересчитанная с поверхности на радиус орбиты
+        if let (Some(h1), Some(h2)) = (a.altitude_km, b.altitude_km) {
+            let r = geo::EARTH_RADIUS_KM;
+            let inertial = haversine_km(a.lat, a.lon, b.lat, b.lon + geo::EARTH_ROTATION_DEG_PER_S * dt);
+            orbital_speeds.push(inertial / dt * 3600.0 * (r + (h1 + h2) / 2.0) / r);
+        }
+    }
+
+    let mean = |xs: &[f64]| (!xs.is_empty()).then(|| xs.iter().sum::<f64>() / xs.len() as f64);
+    let speed_kmh = MinMaxMean {
+        min: speeds.iter().copied().reduce(f64::min),
+        max: speeds.iter().copied().reduce(f64::max),
+        mean: mean(&speeds),
+    };
+
+    let alts: Vec<f64> = pts.iter().filter_map(|p| p.altitude_km).collect();
+    let altitude = match (alts.first(), alts.last()) {
+        (Some(&first_km), Some(&last_km)) if alts.len() >= 2 => {
+            let delta_km = last_km - first_km;
+            let trend = if delta_km > TREND_ALTITUDE_STEADY_KM { "rising" }
+                else if delta_km < -TREND_ALTITUDE_STEADY_KM { "falling" }
+                else { "steady" };
+            Some(AltitudeTrend { first_km, last_km, delta_km, trend })
+        }
+        _ => None,
+    };
+
+    let heading_deg = match pts {
+        [.., a, b] if (a.lat, a.lon) != (b.lat, b.lon) => Some(geo::initial_bearing_deg(a.lat, a.lon, b.lat, b.lon)),
+        _ => None,
+    };
+
+    let mut sorted = dts.clone();
+    sorted.sort_by(f64::total_cmp);
+    let gaps = match sorted.get(sorted.len() / 2) {
+        Some(&median) => pts.windows(2)
+            .map(|w| (w[0].t, w[1].t, (w[1].t - w[0].t).num_milliseconds() as f64 / 1000.0))
+            .filter(|(_, _, dt)| *dt > median * TREND_GAP_FACTOR)
+            .map(|(from, to, dt_sec)| SampleGap { from, to, dt_sec })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let reported: Vec<f64> = pts.iter().filter_map(|p| p.velocity_kmh).collect();
+    let velocity_check = match (mean(&orbital_speeds), mean(&reported)) {
+        (Some(derived), Some(rep)) if rep > 0.0 => {
+            let deviation_pct = (derived - rep).abs() / rep * 100.0;
+            Some(VelocityCheck {
+                derived_mean_kmh: derived,
+                reported_mean_kmh: rep,
+                deviation_pct,
+                consistent: deviation_pct <= TREND_VELOCITY_TOLERANCE_PCT,
+            })
+        }
+        _ => None,
+    };
+
+    let span_sec = match pts {
+        [first, .., last] => (last.t - first.t).num_milliseconds() as f64 / 1000.0,
+        _ => 0.0,
+    };
+
+    TrendStats {
+        window, samples: pts.len(), span_sec, distance_km,
+        speed_kmh, altitude, heading_deg, gaps, velocity_check,
+    }
 }
 
-fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
-    let rlat1 = lat1.to_radians();
-    let rlat2 = lat2.to_radians();
-    let dlat = (lat2 - lat1).to_radians();
-    let dlon = (lon2 - lon1).to_radians();
-    let a = (dlat / 2.0).sin().powi(2) + rlat1.cos() * rlat2.cos() * (dlon / 2.0).sin().powi(2);
-    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
-    6371.0 * c
+#[derive(Deserialize)]
+struct TrackQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    max_points: Option<usize>,
 }
 
-/* ---------- OSDR ---------- */
-async fn osdr_sync(State(st): State<AppState>)
--> Result<Json<Value>, (StatusCode, String)> {
-    let written = fetch_and_store_osdr(&st).await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    Ok(Json(serde_json::json!({ "written": written })))
+const TRACK_MAX_POINTS: usize = 5000;
+const TRACK_MAX_WINDOW_DAYS: i64 = 31;
+
+/// Окно и точки трассы после валидации параметров; общая часть для JSON и экспортов.
+struct TrackWindow {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    total: usize,
+    points: Vec<TrackPoint>,
 }
 
-async fn osdr_list(State(st): State<AppState>)
--> Result<Json<Value>, (StatusCode, String)> {
-    let limit =  std::env::var("OSDR_LIST_LIMIT").ok()
-        .and_then(|s| s.parse::<i64>().ok()).unwrap_or(20);
+async fn load_track(pool: &PgPool, q: &TrackQuery) -> Result<TrackWindow, (StatusCode, String)> {
+    let to = q.to.unwrap_or_else(Utc::now);
+    let from = q.from.unwrap_or(to - chrono::Duration::hours(1));
+    if from >= to {
+        return Err((StatusCode::BAD_REQUEST, "from must be earlier than to".into()));
+    }
+    if to - from > chrono::Duration::days(TRACK_MAX_WINDOW_DAYS) {
+        return Err((StatusCode::BAD_REQUEST, format!("window must not exceed {TRACK_MAX_WINDOW_DAYS} days")));
+    }
+    let max_points = q.max_points.unwrap_or(500);
+    if !(2..=TRACK_MAX_POINTS).contains(&max_points) {
+        return Err((StatusCode::BAD_REQUEST, format!("max_points must be within 2..={TRACK_MAX_POINTS}")));
+    }
 
     let rows = sqlx::query(
-        "SELECT id, dataset_id, title, status, updated_at, inserted_at, raw
-         FROM osdr_items
-         ORDER BY inserted_at DESC
-         LIMIT $1"
-    ).bind(limit).fetch_all(&st.pool).await
+        "SELECT COALESCE(position_at, fetched_at) AS t, latitude, longitude, altitude_km, velocity_kmh
+         FROM iss_fetch_log
+         WHERE latitude IS NOT NULL AND longitude IS NOT NULL
+           AND COALESCE(position_at, fetched_at) >= $1 AND COALESCE(position_at, fetched_at) < $2
+         ORDER BY t"
+    ).bind(from).bind(to).fetch_all(pool).await
      .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let out: Vec<Value> = rows.into_iter().map(|r| {
-        serde_json::json!({
-            "id": r.get::<i64,_>("id"),
-            "dataset_id": r.get::<Option<String>,_>("dataset_id"),
-            "title": r.get::<Option<String>,_>("title"),
-            "status": r.get::<Option<String>,_>("status"),
-            "updated_at": r.get::<Option<DateTime<Utc>>,_>("updated_at"),
-            "inserted_at": r.get::<DateTime<Utc>, _>("inserted_at"),
-            "raw": r.get::<Value,_>("raw"),
-        })
+    let points: Vec<TrackPoint> = rows.iter().map(|r| TrackPoint {
+        t: r.get("t"),
+        lat: r.get("latitude"),
+        lon: r.get("longitude"),
+        altitude_km: r.get("altitude_km"),
+        velocity_kmh: r.get("velocity_kmh"),
     }).collect();
-
-    Ok(Json(serde_json::json!({ "items": out })))
+    let total = points.len();
+    let points = geo::lttb(points, max_points);
+    Ok(TrackWindow { from, to, total, points })
 }
 
-/* ---------- Универсальная витрина space_cache ---------- */
-
-async fn space_latest(Path(src): Path<String>, State(st): State<AppState>)
+async fn iss_track(Query(q): Query<TrackQuery>, State(st): State<AppState>)
 -> Result<Json<Value>, (StatusCode, String)> {
-    let row = sqlx::query(
-        "SELECT fetched_at, payload FROM space_cache
-         WHERE source = $1 ORDER BY id DESC LIMIT 1"
-    ).bind(&src).fetch_optional(&st.pool).await
-     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let w = load_track(&st.pool, &q).await?;
+    let returned = w.points.len();
+    let segments = geo::split_antimeridian(w.points);
+    Ok(Json(serde_json::json!({
+        "from": w.from, "to": w.to,
+        "total_points": w.total, "returned_points": returned,
+        "segments": segments,
+    })))
+}
 
-    if let Some(r) = row {
-        let fetched_at: DateTime<Utc> = r.get("fetched_at");
-        let payload: Value = r.get("payload");
-        return Ok(Json(serde_json::json!({ "source": src, "fetched_at": fetched_at, "payload": payload })));
-    }
-    Ok(Json(serde_json::json!({ "source": src, "message":"no data" })))
+async fn iss_track_geojson(Query(q): Query<TrackQuery>, State(st): State<AppState>)
+-> Result<impl IntoResponse, (StatusCode, String)> {
+    let w = load_track(&st.pool, &q).await?;
+    let segments = geo::split_antimeridian(w.points.clone());
+    let body = geo::to_geojson(&segments, &w.points);
+    Ok(([(header::CONTENT_TYPE, "application/geo+json")], body.to_string()))
 }
 
-async fn space_refresh(Query(q): Query<HashMap<String,String>>, State(st): State<AppState>)
--> Result<Json<Value>, (StatusCode, String)> {
-    let list = q.get("src").cloned().unwrap_or_else(|| "apod,neo,flr,cme,spacex".to_string());
-    let mut done = Vec::new();
-    for s in list.split(',').map(|x| x.trim().to_lowercase()) {
-        match s.as_str() {
-            "apod"   => { let _ = fetch_apod(&st).await;       done.push("apod"); }
-            "neo"    => { let _ = fetch_neo_feed(&st).await;   done.push("neo"); }
-            "flr"    => { let _ = fetch_donki_flr(&st).await;  done.push("flr"); }
-            "cme"    => { let _ = fetch_donki_cme(&st).await;  done.push("cme"); }
-            "spacex" => { let _ = fetch_spacex_next(&st).await; done.push("spacex"); }
-            _ => {}
+async fn iss_track_kml(Query(q): Query<TrackQuery>, State(st): State<AppState>)
+-> Result<impl IntoResponse, (StatusCode, String)> {
+    let w = load_track(&st.pool, &q).await?;
+    let segments = geo::split_antimeridian(w.points.clone());
+    let body = geo::to_kml(&segments, &w.points);
+    Ok(([(header::CONTENT_TYPE, "application/vnd.google-earth.kml+xml")], body))
+}
+
+/* ---------- ISS: прогноз по TLE ---------- */
+
+const ISS_NORAD_ID: u32 = 25544;
+
+/// Свежий TLE: из сети, при ошибке — из локального файла.
+async fn fetch_and_store_tle(st: &AppState) -> anyhow::Result<u64> {
+    let mut last_err = None;
+    let settings = st.settings();
+    let cfg = &settings.config.tle;
+    if let Some(url) = &cfg.url {
+        match fetch_tle_text(&st.http, cfg).await {
+            // 304: TLE на CelesTrak не обновился
+            Ok(None) => return Ok(0),
+            Ok(Some((text, validators))) => match sgp4::Tle::find_in(&text, Some(ISS_NORAD_ID)) {
+                Ok(tle) => {
+                    let written = store_tle(&st.pool, url, &tle, cfg.retention_days).await?;
+                    st.http.remember(validators).await?;
+                    return Ok(written);
+                }
+                Err(e) => { warn!("tle from {url} is invalid: {e}"); last_err = Some(e.into()); }
+            },
+            Err(e) => { warn!("tle fetch from {url} failed: {e}"); last_err = Some(e); }
         }
     }
-    Ok(Json(serde_json::json!({ "refreshed": done })))
+    if let Some(path) = &settings.tle_file {
+        let text = tokio::fs::read_to_string(path).await?;
+        let tle = sgp4::Tle::find_in(&text, Some(ISS_NORAD_ID))?;
+        return store_tle(&st.pool, &format!("file://{path}"), &tle, cfg.retention_days).await;
+    }
+    Err(last_err.unwrap_or_else(|| anyhow::anyhow!("neither ISS_TLE_URL nor ISS_TLE_FILE is set")))
 }
 
-async fn latest_from_cache(pool: &PgPool, src: &str) -> Value {
-    sqlx::query("SELECT fetched_at, payload FROM space_cache WHERE source=$1 ORDER BY id DESC LIMIT 1")
-        .bind(src)
-        .fetch_optional(pool).await.ok().flatten()
-        .map(|r| serde_json::json!({"at": r.get::<DateTime<Utc>,_>("fetched_at"), "payload": r.get::<Value,_>("payload")}))
-        .unwrap_or(serde_json::json!({}))
+/// `None` — с прошлого раза не менялся.
+async fn fetch_tle_text(http: &Http, cfg: &config::SourceConfig) -> anyhow::Result<Option<(String, Validators)>> {
+    let Some((resp, validators)) = http.send_conditional(cfg.request(&http.client()), cfg.nasa()).await? else {
+        return Ok(None);
+    };
+    if !resp.status().is_success() {
+        anyhow::bail!("TLE request status {}", resp.status());
+    }
+    Ok(Some((resp.text().await?, validators)))
 }
 
-async fn space_summary(State(st): State<AppState>)
+/// Число новых строк: 0, если такой TLE уже есть.
+async fn store_tle(pool: &PgPool, source: &str, tle: &sgp4::Tle, retention_days: Option<u32>) -> anyhow::Result<u64> {
+    let written = sqlx::query(
+        "INSERT INTO iss_tle(source, name, norad_id, epoch, line1, line2)
+         VALUES ($1,$2,$3,$4,$5,$6)
+         ON CONFLICT (line1, line2) DO NOTHING"
+    ).bind(source).bind(&tle.name).bind(tle.norad_id as i32).bind(tle.epoch)
+     .bind(&tle.line1).bind(&tle.line2).execute(pool).await?.rows_affected();
+    if let Some(days) = retention_days {
+        // самый свежий по эпохе TLE оставляем всегда — по нему считается прогноз
+        sqlx::query(
+            "DELETE FROM iss_tle WHERE fetched_at < now() - make_interval(days => $1)
+               AND id <> (SELECT id FROM iss_tle ORDER BY epoch DESC LIMIT 1)"
+        ).bind(days as i32).execute(pool).await?;
+    }
+    Ok(written)
+}
+
+/// Модель по самому свежему (по эпохе) TLE из базы.
+async fn latest_sgp4(pool: &PgPool) -> anyhow::Result<Option<(sgp4::Tle, sgp4::Sgp4)>> {
+    let row = sqlx::query("SELECT name, line1, line2 FROM iss_tle ORDER BY epoch DESC LIMIT 1")
+        .fetch_optional(pool).await?;
+    let Some(r) = row else { return Ok(None) };
+    let tle = sgp4::Tle::parse(
+        r.get::<Option<String>,_>("name").as_deref(), r.get("line1"), r.get("line2"),
+    )?;
+    let model = sgp4::Sgp4::new(&tle)?;
+    Ok(Some((tle, model)))
+}
+
+/// Положение МКС по модели на момент `at` в той же форме, что и замеры.
+fn predict_position(model: &sgp4::Sgp4, at: DateTime<Utc>) -> Result<IssPosition, sgp4::Sgp4Error> {
+    let sv = model.at(at)?;
+    let (lat, lon, alt) = sgp4::ecef_to_geodetic(sgp4::teme_to_ecef(sv.r, at));
+    let speed = (sv.v[0].powi(2) + sv.v[1].powi(2) + sv.v[2].powi(2)).sqrt() * 3600.0;
+    Ok(IssPosition {
+        latitude: lat,
+        longitude: lon,
+        altitude_km: Some(alt),
+        velocity_kmh: Some(speed),
+        visibility: Some(if passes::is_sunlit(sv.r, passes::sun_eci(at).0) { "daylight" } else { "eclipsed" }.to_string()),
+        footprint_km: Some(sgp4::footprint_km(alt)),
+        timestamp: Some(at),
+    })
+}
+
+async fn require_sgp4(pool: &PgPool) -> Result<(sgp4::Tle, sgp4::Sgp4), (StatusCode, String)> {
+    latest_sgp4(pool).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::SERVICE_UNAVAILABLE, "no TLE loaded yet".to_string()))
+}
+
+fn tle_json(tle: &sgp4::Tle, at: DateTime<Utc>) -> Value {
+    serde_json::json!({
+        "name": tle.name, "norad_id": tle.norad_id, "epoch": tle.epoch,
+        "age_days": (at - tle.epoch).num_seconds() as f64 / 86400.0,
+        "line1": tle.line1, "line2": tle.line2,
+    })
+}
+
+#[derive(Deserialize)]
+struct PredictQuery {
+    at: Option<DateTime<Utc>>,
+}
+
+async fn iss_predict(Query(q): Query<PredictQuery>, State(st): State<AppState>)
 -> Result<Json<Value>, (StatusCode, String)> {
-    let apod   = latest_from_cache(&st.pool, "apod").await;
-    let neo    = latest_from_cache(&st.pool, "neo").await;
-    let flr    = latest_from_cache(&st.pool, "flr").await;
-    let cme    = latest_from_cache(&st.pool, "cme").await;
-    let spacex = latest_from_cache(&st.pool, "spacex").await;
+    let at = q.at.unwrap_or_else(Utc::now);
+    let (tle, model) = require_sgp4(&st.pool).await?;
+    let position = predict_position(&model, at)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    Ok(Json(serde_json::json!({ "at": at, "position": position, "tle": tle_json(&tle, at) })))
+}
 
-    let iss_last = sqlx::query("SELECT fetched_at,payload FROM iss_fetch_log ORDER BY id DESC LIMIT 1")
-        .fetch_optional(&st.pool).await.ok().flatten()
-        .map(|r| serde_json::json!({"at": r.get::<DateTime<Utc>,_>("fetched_at"), "payload": r.get::<Value,_>("payload")}))
-        .unwrap_or(serde_json::json!({}));
+#[derive(Deserialize)]
+struct PredictTrackQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    step: Option<i64>,
+}
 
-    let osdr_count: i64 = sqlx::query("SELECT count(*) AS c FROM osdr_items")
-        .fetch_one(&st.pool).await.map(|r| r.get::<i64,_>("c")).unwrap_or(0);
+const PREDICT_MAX_POINTS: i64 = 5000;
+const PREDICT_MAX_WINDOW_DAYS: i64 = 3;
 
+async fn iss_predict_track(Query(q): Query<PredictTrackQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let from = q.from.unwrap_or_else(Utc::now);
+    let to = q.to.unwrap_or(from + chrono::Duration::minutes(93));
+    let step = q.step.unwrap_or(60);
+    if from >= to {
+        return Err((StatusCode::BAD_REQUEST, "from must be earlier than to".into()));
+    }
+    if to - from > chrono::Duration::days(PREDICT_MAX_WINDOW_DAYS) {
+        return Err((StatusCode::BAD_REQUEST, format!("window must not exceed {PREDICT_MAX_WINDOW_DAYS} days")));
+    }
+    if !(1..=3600).contains(&step) {
+        return Err((StatusCode::BAD_REQUEST, "step must be within 1..=3600 seconds".into()));
+    }
+    if (to - from).num_seconds() / step > PREDICT_MAX_POINTS {
+        return Err((StatusCode::BAD_REQUEST, format!("too many points, at most {PREDICT_MAX_POINTS}; increase step")));
+    }
+
+    let (tle, model) = require_sgp4(&st.pool).await?;
+    let mut points = Vec::new();
+    let mut t = from;
+    while t <= to {
+        let p = predict_position(&model, t)
+            .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+        points.push(TrackPoint {
+            t, lat: p.latitude, lon: p.longitude, altitude_km: p.altitude_km, velocity_kmh: p.velocity_kmh,
+        });
+        t += chrono::Duration::seconds(step);
+    }
+    let count = points.len();
     Ok(Json(serde_json::json!({
-        "apod": apod, "neo": neo, "flr": flr, "cme": cme, "spacex": spacex,
-        "iss": iss_last, "osdr_count": osdr_count
+        "from": from, "to": to, "step": step, "points": count,
+        "segments": geo::split_antimeridian(points),
+        "tle": tle_json(&tle, from),
     })))
 }
 
-/* ---------- Фетчеры и запись ---------- */
+#[derive(Deserialize)]
+struct PassesQuery {
+    lat: f64,
+    lon: f64,
+    /// высота наблюдателя над эллипсоидом, м
+    alt: Option<f64>,
+    days: Option<i64>,
+    min_el: Option<f64>,
+}
 
-async fn write_cache(pool: &PgPool, source: &str, payload: Value) -> anyhow::Result<()> {
-    sqlx::query("INSERT INTO space_cache(source, payload) VALUES ($1,$2)")
-        .bind(source).bind(payload).execute(pool).await?;
-    Ok(())
+const PASSES_MAX_DAYS: i64 = 10;
+/// Наблюдатель на земле: от берега Мёртвого моря до выше Эвереста.
+const PASSES_MIN_ALT_M: f64 = -500.0;
+const PASSES_MAX_ALT_M: f64 = 10000.0;
+
+async fn iss_passes(Query(q): Query<PassesQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    if !(-90.0..=90.0).contains(&q.lat) || !(-180.0..=180.0).contains(&q.lon) {
+        return Err((StatusCode::BAD_REQUEST, "lat must be within -90..=90 and lon within -180..=180".into()));
+    }
+    let days = q.days.unwrap_or(3);
+    if !(1..=PASSES_MAX_DAYS).contains(&days) {
+        return Err((StatusCode::BAD_REQUEST, format!("days must be within 1..={PASSES_MAX_DAYS}")));
+    }
+    let min_el = q.min_el.unwrap_or(passes::DEFAULT_MIN_ELEVATION_DEG);
+    if !(0.0..90.0).contains(&min_el) {
+        return Err((StatusCode::BAD_REQUEST, "min_el must be within 0..90".into()));
+    }
+    let alt_m = q.alt.unwrap_or(0.0);
+    if !(PASSES_MIN_ALT_M..=PASSES_MAX_ALT_M).contains(&alt_m) {
+        return Err((StatusCode::BAD_REQUEST, format!("alt must be within {PASSES_MIN_ALT_M}..={PASSES_MAX_ALT_M} m")));
+    }
+    let observer = passes::Observer { lat_deg: q.lat, lon_deg: q.lon, alt_km: alt_m / 1000.0 };
+
+    let (tle, model) = require_sgp4(&st.pool).await?;
+    let from = Utc::now();
+    let to = from + chrono::Duration::days(days);
+    let found = tokio::task::spawn_blocking(move || passes::find_passes(&model, &observer, from, to, min_el))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let next_visible = found.iter().find(|p| p.visibility == "visible").cloned();
+
+    Ok(Json(serde_json::json!({
+        "observer": { "lat": q.lat, "lon": q.lon, "alt_m": alt_m },
+        "from": from, "to": to, "min_elevation_deg": min_el,
+        "passes": found,
+        "next_visible": next_visible,
+        "tle": tle_json(&tle, from),
+    })))
 }
 
-// APOD
-async fn fetch_apod(st: &AppState) -> anyhow::Result<()> {
-    let url = "https://api.nasa.gov/planetary/apod";
-    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
-    let mut req = client.get(url).query(&[("thumbs","true")]);
-    if !st.nasa_key.is_empty() { req = req.query(&[("api_key",&st.nasa_key)]); }
-    let json: Value = req.send().await?.json().await?;
-    write_cache(&st.pool, "apod", json).await
-}
-
-// NeoWs
-async fn fetch_neo_feed(st: &AppState) -> anyhow::Result<()> {
-    let today = Utc::now().date_naive();
-    let start = today - chrono::Days::new(2);
-    let url = "https://api.nasa.gov/neo/rest/v1/feed";
-    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
-    let mut req = client.get(url).query(&[
-        ("start_date", start.to_string()),
-        ("end_date", today.to_string()),
-    ]);
-    if !st.nasa_key.is_empty() { req = req.query(&[("api_key",&st.nasa_key)]); }
-    let json: Value = req.send().await?.json().await?;
-    write_cache(&st.pool, "neo", json).await
-}
-
-// DONKI объединённая
-async fn fetch_donki(st: &AppState) -> anyhow::Result<()> {
-    let _ = fetch_donki_flr(st).await;
-    let _ = fetch_donki_cme(st).await;
-    Ok(())
+fn num(v: &Value) -> Option<f64> {
+    if let Some(x) = v.as_f64() { return Some(x); }
+    if let Some(s) = v.as_str() { return s.parse::<f64>().ok(); }
+    None
 }
-async fn fetch_donki_flr(st: &AppState) -> anyhow::Result<()> {
-    let (from,to) = last_days(5);
-    let url = "https://api.nasa.gov/DONKI/FLR";
-    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
-    let mut req = client.get(url).query(&[("startDate",from),("endDate",to)]);
-    if !st.nasa_key.is_empty() { req = req.query(&[("api_key",&st.nasa_key)]); }
-    let json: Value = req.send().await?.json().await?;
-    write_cache(&st.pool, "flr", json).await
+
+
+/* ---------- OSDR ---------- */
+async fn osdr_sync(State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let stats = fetch_and_store_osdr(&st).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({ "written": stats.written, "shapes": stats.shapes })))
 }
-async fn fetch_donki_cme(st: &AppState) -> anyhow::Result<()> {
-    let (from,to) = last_days(5);
-    let url = "https://api.nasa.gov/DONKI/CME";
-    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
-    let mut req = client.get(url).query(&[("startDate",from),("endDate",to)]);
-    if !st.nasa_key.is_empty() { req = req.query(&[("api_key",&st.nasa_key)]); }
-    let json: Value = req.send().await?.json().await?;
-    write_cache(&st.pool, "cme", json).await
+
+#[derive(Deserialize)]
+struct OsdrListQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+    cursor: Option<String>,
+    status: Option<String>,
+    updated_since: Option<DateTime<Utc>>,
+    updated_before: Option<DateTime<Utc>>,
+    q: Option<String>,
+    sort: Option<String>,
 }
 
-// SpaceX
-async fn fetch_spacex_next(st: &AppState) -> anyhow::Result<()> {
-    let url = "https://api.spacexdata.com/v4/launches/next";
-    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
-    let json: Value = client.get(url).send().await?.json().await?;
-    write_cache(&st.pool, "spacex", json).await
+/// Ключ сортировки /osdr/list: SQL-выражение (без NULL, чтобы работал keyset) и его тип для курсора.
+#[derive(Clone, Copy)]
+enum OsdrSort { Inserted, Updated, Title, DatasetId, Id }
+
+impl OsdrSort {
+    fn parse(s: &str) -> Option<(Self, bool)> {
+        let (desc, name) = match s.strip_prefix('-') { Some(n) => (true, n), None => (false, s) };
+        let key = match name {
+            "inserted_at" => Self::Inserted,
+            "updated_at" => Self::Updated,
+            "title" => Self::Title,
+            "dataset_id" => Self::DatasetId,
+            "id" => Self::Id,
+            _ => return None,
+        };
+        Some((key, desc))
+    }
+    fn expr(self) -> &'static str {
+        match self {
+            Self::Inserted => "inserted_at",
+            Self::Updated => "COALESCE(updated_at, '-infinity'::timestamptz)",
+            Self::Title => "COALESCE(title, '')",
+            Self::DatasetId => "COALESCE(dataset_id, '')",
+            Self::Id => "id",
+        }
+    }
+    fn sql_type(self) -> &'static str {
+        match self {
+            Self::Inserted | Self::Updated => "timestamptz",
+            Self::Title | Self::DatasetId => "text",
+            Self::Id => "bigint",
+        }
+    }
 }
 
-fn last_days(n: i64) -> (String,String) {
-    let to = Utc::now().date_naive();
-    let from = to - chrono::Days::new(n as u64);
-    (from.to_string(), to.to_string())
+/// Курсор keyset-пагинации: сортировка, значение ключа последней строки и её id.
+#[derive(Serialize, Deserialize)]
+struct OsdrCursor { s: String, v: String, id: i64 }
+
+const OSDR_LIST_MAX_LIMIT: i64 = 500;
+
+async fn osdr_list(Query(q): Query<OsdrListQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let bad = |m: String| (StatusCode::BAD_REQUEST, m);
+
+    let limit = q.limit.unwrap_or(st.settings().osdr_list_limit);
+    if !(1..=OSDR_LIST_MAX_LIMIT).contains(&limit) {
+        return Err(bad(format!("limit must be within 1..={OSDR_LIST_MAX_LIMIT}")));
+    }
+    if q.offset.is_some_and(|o| o < 0) {
+        return Err(bad("offset must be >= 0".into()));
+    }
+    if q.offset.is_some() && q.cursor.is_some() {
+        return Err(bad("use either cursor or offset, not both".into()));
+    }
+    if let (Some(a), Some(b)) = (q.updated_since, q.updated_before) {
+        if a >= b { return Err(bad("updated_since must be earlier than updated_before".into())); }
+    }
+
+    let sort_name = q.sort.clone().unwrap_or_else(|| "-inserted_at".to_string());
+    let (sort, desc) = OsdrSort::parse(&sort_name)
+        .ok_or_else(|| bad(format!("unknown sort '{sort_name}'")))?;
+    let cursor = match &q.cursor {
+        Some(c) => {
+            let cur: OsdrCursor = hex_decode(c)
+                .and_then(|b| serde_json::from_slice(&b).ok())
+                .ok_or_else(|| bad("malformed cursor".into()))?;
+            if cur.s != sort_name { return Err(bad("cursor was issued for a different sort".into())); }
+            Some(cur)
+        }
+        None => None,
+    };
+
+    let mut qb = sqlx::QueryBuilder::<sqlx::Postgres>::new(format!(
+        "SELECT id, dataset_id, title, status, updated_at, inserted_at, raw, ({})::text AS sort_key
+         FROM osdr_items WHERE TRUE", sort.expr()
+    ));
+    if let Some(s) = &q.status {
+        qb.push(" AND status = ").push_bind(s.clone());
+    }
+    if let Some(t) = q.updated_since {
+        qb.push(" AND updated_at >= ").push_bind(t);
+    }
+    if let Some(t) = q.updated_before {
+        qb.push(" AND updated_at < ").push_bind(t);
+    }
+    if let Some(text) = q.q.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        let pattern = format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        qb.push(" AND title ILIKE ").push_bind(pattern);
+    }
+    if let Some(cur) = &cursor {
+        qb.push(format!(" AND ({}, id) {} (CAST(", sort.expr(), if desc { "<" } else { ">" }))
+            .push_bind(cur.v.clone())
+            .push(format!(" AS {}), ", sort.sql_type()))
+            .push_bind(cur.id)
+            .push(")");
+    }
+    let dir = if desc { "DESC" } else { "ASC" };
+    qb.push(format!(" ORDER BY {} {dir}, id {dir} LIMIT ", sort.expr())).push_bind(limit + 1);
+    if let Some(o) = q.offset {
+        qb.push(" OFFSET ").push_bind(o);
+    }
+
+    let mut rows = qb.build().fetch_all(&st.pool).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    let next_cursor = if has_more {
+        rows.last().map(|r| {
+            let cur = OsdrCursor { s: sort_name.clone(), v: r.get("sort_key"), id: r.get("id") };
+            hex_encode(&serde_json::to_vec(&cur).unwrap_or_default())
+        })
+    } else { None };
+
+    let out: Vec<Value> = rows.iter().map(osdr_row_json).collect();
+
+    Ok(Json(serde_json::json!({
+        "items": out, "limit": limit, "sort": sort_name, "next_cursor": next_cursor
+    })))
+}
+
+// Текстовые поля из raw, попадающие в search_tsv. Только иммутабельные выражения —
+// они входят в GENERATED-колонку.
+const OSDR_TEXT_DESCRIPTION: &str =
+    "COALESCE(raw->>'description', raw->>'study description', raw->>'Study Description', '')";
+const OSDR_TEXT_DETAILS: &str =
+    "COALESCE(raw->>'organism', raw->>'Organism', '') || ' ' ||
+     COALESCE(raw->>'assay type', raw->>'assay_type', raw->>'Study Assay Technology Type', '')";
+
+/// SQL-выражение `expr`, экранированное для HTML. Тексты OSDR приходят от апстрима как есть,
+/// поэтому в ts_headline идут уже экранированными: в title_highlight и snippet единственная
+/// разметка — <mark>…</mark>, остальное безопасно вставлять как HTML.
+fn sql_html_escape(expr: &str) -> String {
+    format!("replace(replace(replace(replace(replace({expr}, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '\"', '&quot;'), '''', '&#39;')")
+}
+
+#[derive(Deserialize)]
+struct OsdrSearchQuery {
+    q: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+async fn osdr_search(Query(q): Query<OsdrSearchQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let text = q.q.as_deref().map(str::trim).unwrap_or_default();
+    if text.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "q is required".into()));
+    }
+    let limit = q.limit.unwrap_or(20);
+    if !(1..=100).contains(&limit) {
+        return Err((StatusCode::BAD_REQUEST, "limit must be within 1..=100".into()));
+    }
+    let offset = q.offset.unwrap_or(0).max(0);
+
+    let rows = sqlx::query(&format!(
+        "SELECT id, dataset_id, title, status, updated_at,
+                ts_rank_cd(search_tsv, query) AS rank,
+                ts_headline('english', {title}, query,
+                            'StartSel=<mark>, StopSel=</mark>, HighlightAll=true') AS title_hl,
+                ts_headline('english', {text}, query,
+                            'StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=30, MinWords=10') AS snippet
+         FROM osdr_items, websearch_to_tsquery('english', $1) AS query
+         WHERE search_tsv @@ query
+         ORDER BY rank DESC, id DESC
+         LIMIT $2 OFFSET $3",
+        title = sql_html_escape("COALESCE(title, '')"),
+        text = sql_html_escape(&format!("{OSDR_TEXT_DESCRIPTION} || ' ' || {OSDR_TEXT_DETAILS}")),
+    )).bind(text).bind(limit).bind(offset).fetch_all(&st.pool).await
+     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let out: Vec<Value> = rows.into_iter().map(|r| {
+        serde_json::json!({
+            "id": r.get::<i64,_>("id"),
+            "dataset_id": r.get::<Option<String>,_>("dataset_id"),
+            "title": r.get::<Option<String>,_>("title"),
+            "status": r.get::<Option<String>,_>("status"),
+            "updated_at": r.get::<Option<DateTime<Utc>>,_>("updated_at"),
+            "rank": r.get::<f32,_>("rank"),
+            "title_highlight": r.get::<String,_>("title_hl"),
+            "snippet": r.get::<String,_>("snippet"),
+        })
+    }).collect();
+
+    Ok(Json(serde_json::json!({ "q": text, "items": out })))
+}
+
+fn osdr_row_json(r: &sqlx::postgres::PgRow) -> Value {
+    serde_json::json!({
+        "id": r.get::<i64,_>("id"),
+        "dataset_id": r.get::<Option<String>,_>("dataset_id"),
+        "title": r.get::<Option<String>,_>("title"),
+        "status": r.get::<Option<String>,_>("status"),
+        "updated_at": r.get::<Option<DateTime<Utc>>,_>("updated_at"),
+        "inserted_at": r.get::<DateTime<Utc>, _>("inserted_at"),
+        "raw": r.get::<Value,_>("raw"),
+    })
+}
+
+#[derive(Deserialize)]
+struct OsdrItemQuery {
+    #[serde(default)]
+    refresh: bool,
+}
+
+async fn osdr_item(Path(dataset_id): Path<String>, Query(q): Query<OsdrItemQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    if q.refresh {
+        // идёт в URL апстрима: только accession, без '/', '?', '#' и т.п.
+        if !is_osdr_accession(&dataset_id) {
+            return Err((StatusCode::BAD_REQUEST, format!("dataset id must look like OSD-<number>, got '{dataset_id}'")));
+        }
+        fetch_and_store_osdr_one(&st, &dataset_id).await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+    }
+
+    let row = sqlx::query(
+        "SELECT id, dataset_id, title, status, updated_at, inserted_at, raw
+         FROM osdr_items WHERE dataset_id = $1"
+    ).bind(&dataset_id).fetch_optional(&st.pool).await
+     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    match row {
+        Some(r) => Ok(Json(osdr_row_json(&r))),
+        None => Err((StatusCode::NOT_FOUND, format!("dataset {dataset_id} not found"))),
+    }
+}
+
+async fn osdr_history(Path(dataset_id): Path<String>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let rows = sqlx::query(
+        "SELECT version, content_hash, title, status, updated_at, recorded_at, raw
+         FROM osdr_item_versions
+         WHERE dataset_id = $1
+         ORDER BY version ASC"
+    ).bind(&dataset_id).fetch_all(&st.pool).await
+     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if rows.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("dataset {dataset_id} not found")));
+    }
+
+    let mut prev: Option<Value> = None;
+    let mut out = Vec::with_capacity(rows.len());
+    for r in rows {
+        let raw: Value = r.get("raw");
+        let diff = prev.as_ref().map(|p| {
+            let mut ops = Vec::new();
+            json_diff(p, &raw, "", &mut ops);
+            ops
+        });
+        out.push(serde_json::json!({
+            "version": r.get::<i32,_>("version"),
+            "content_hash": r.get::<String,_>("content_hash"),
+            "title": r.get::<Option<String>,_>("title"),
+            "status": r.get::<Option<String>,_>("status"),
+            "updated_at": r.get::<Option<DateTime<Utc>>,_>("updated_at"),
+            "recorded_at": r.get::<DateTime<Utc>,_>("recorded_at"),
+            "raw": raw.clone(),
+            "diff": diff,
+        }));
+        prev = Some(raw);
+    }
+
+    Ok(Json(serde_json::json!({ "dataset_id": dataset_id, "versions": out })))
+}
+
+/* ---------- NEO ---------- */
+
+#[derive(Deserialize)]
+struct NeoApproachQuery {
+    from: Option<String>,
+    to: Option<String>,
+    hazardous: Option<bool>,
+    max_miss_km: Option<f64>,
+    sort: Option<String>,
+    limit: Option<i64>,
+}
+
+const NEO_MAX_WINDOW_DAYS: i64 = 366;
+const NEO_MAX_LIMIT: i64 = 1000;
+
+/// Момент RFC 3339 или дата YYYY-MM-DD (полночь UTC).
+fn parse_instant(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Some(t.with_timezone(&Utc));
+    }
+    Some(s.parse::<chrono::NaiveDate>().ok()?.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+/// Окно по умолчанию, дней: `Ahead` — от `from` (сейчас) вперёд, `Back` — до `to` (сейчас) назад.
+#[derive(Clone, Copy)]
+enum Span { Ahead(i64), Back(i64) }
+
+/// from/to из запроса (даты или RFC 3339); недостающий конец — по `default_span`.
+/// Окно непустое и не длиннее `max_days`.
+fn parse_window(from: Option<&str>, to: Option<&str>, default_span: Span, max_days: i64)
+-> Result<(DateTime<Utc>, DateTime<Utc>), (StatusCode, String)> {
+    let bad = |m: String| (StatusCode::BAD_REQUEST, m);
+    let instant = |name: &str, v: Option<&str>| v.map(|s| {
+        parse_instant(s).ok_or_else(|| bad(format!("{name} must be a date (YYYY-MM-DD) or an RFC 3339 timestamp")))
+    }).transpose();
+    let (from, to) = (instant("from", from)?, instant("to", to)?);
+    let (from, to) = match default_span {
+        Span::Ahead(days) => {
+            let from = from.unwrap_or_else(Utc::now);
+            (from, to.unwrap_or(from + chrono::Duration::days(days)))
+        }
+        Span::Back(days) => {
+            let to = to.unwrap_or_else(Utc::now);
+            (from.unwrap_or(to - chrono::Duration::days(days)), to)
+        }
+    };
+    if from >= to {
+        return Err(bad("from must be earlier than to".into()));
+    }
+    if to - from > chrono::Duration::days(max_days) {
+        return Err(bad(format!("window must not exceed {max_days} days")));
+    }
+    Ok((from, to))
+}
+
+fn parse_limit(limit: Option<i64>, default: i64, max: i64) -> Result<i64, (StatusCode, String)> {
+    let limit = limit.unwrap_or(default);
+    if !(1..=max).contains(&limit) {
+        return Err((StatusCode::BAD_REQUEST, format!("limit must be within 1..={max}")));
+    }
+    Ok(limit)
+}
+
+async fn neo_approaches(Query(q): Query<NeoApproachQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let bad = |m: String| (StatusCode::BAD_REQUEST, m);
+    let (from, to) = parse_window(q.from.as_deref(), q.to.as_deref(), Span::Ahead(7), NEO_MAX_WINDOW_DAYS)?;
+    if q.max_miss_km.is_some_and(|m| !m.is_finite() || m <= 0.0) {
+        return Err(bad("max_miss_km must be positive".into()));
+    }
+    let (sort, desc) = match q.sort.as_deref() {
+        Some(s) => neo::ApproachSort::parse(s).ok_or_else(|| bad(
+            "sort must be one of approach_at, miss_km, velocity_kps, diameter_km (prefix with - for descending)".into()
+        ))?,
+        None => (neo::ApproachSort::Time, false),
+    };
+    let limit = parse_limit(q.limit, 100, NEO_MAX_LIMIT)?;
+
+    let filter = neo::ApproachFilter { from, to, hazardous: q.hazardous, max_miss_km: q.max_miss_km, sort, desc, limit };
+    let (total, items) = neo::approaches(&st.pool, &filter).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({ "from": from, "to": to, "total": total, "items": items })))
+}
+
+async fn neo_object(Path(id): Path<String>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    neo::object(&st.pool, &id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, format!("unknown neo '{id}'")))
+}
+
+/* ---------- DONKI ---------- */
+
+#[derive(Deserialize)]
+struct DonkiListQuery {
+    /// Через запятую.
+    #[serde(rename = "type")]
+    types: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<i64>,
+}
+
+const DONKI_EVENT_TYPES: &[&str] = &["FLR", "CME", "GST", "SEP", "IPS", "HSS", "RBE", "MPC"];
+const DONKI_MAX_WINDOW_DAYS: i64 = 366;
+const DONKI_MAX_LIMIT: i64 = 1000;
+
+/// Окно (по умолчанию последние 30 дней) и limit; типы разбирает вызывающий.
+fn donki_filter(q: &DonkiListQuery, types: Vec<String>) -> Result<donki::TimeFilter, (StatusCode, String)> {
+    let (from, to) = parse_window(q.from.as_deref(), q.to.as_deref(), Span::Back(30), DONKI_MAX_WINDOW_DAYS)?;
+    let limit = parse_limit(q.limit, 100, DONKI_MAX_LIMIT)?;
+    Ok(donki::TimeFilter { types, from, to, limit })
+}
+
+fn split_types(v: &Option<String>) -> Vec<String> {
+    v.as_deref().unwrap_or_default().split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect()
+}
+
+async fn donki_events(Query(q): Query<DonkiListQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let types: Vec<String> = split_types(&q.types).iter().map(|t| t.to_uppercase()).collect();
+    if let Some(t) = types.iter().find(|t| !DONKI_EVENT_TYPES.contains(&t.as_str())) {
+        return Err((StatusCode::BAD_REQUEST, format!("unknown type '{t}', known: {}", DONKI_EVENT_TYPES.join(", "))));
+    }
+    let f = donki_filter(&q, types)?;
+    let (total, items) = donki::events(&st.pool, &f).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({ "from": f.from, "to": f.to, "total": total, "items": items })))
+}
+
+#[derive(Deserialize)]
+struct FlareStatsQuery {
+    from: Option<String>,
+    to: Option<String>,
+    bucket: Option<String>,
+}
+
+/// Солнечный цикл — около 11 лет.
+const FLARE_STATS_MAX_DAYS: i64 = 4018;
+
+async fn donki_flare_stats(Query(q): Query<FlareStatsQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let (from, to) = parse_window(q.from.as_deref(), q.to.as_deref(), Span::Back(90), FLARE_STATS_MAX_DAYS)?;
+    let bucket = donki::Bucket::parse(q.bucket.as_deref().unwrap_or("day"))
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "bucket must be one of day, week, month".to_string()))?;
+    let stats = donki::flare_stats(&st.pool, from, to, bucket).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(stats))
+}
+
+async fn donki_notifications(Query(q): Query<DonkiListQuery>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let types = split_types(&q.types).iter().map(|t| t.to_lowercase()).collect();
+    let f = donki_filter(&q, types)?;
+    let (total, items) = donki::notifications(&st.pool, &f).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({ "from": f.from, "to": f.to, "total": total, "items": items })))
+}
+
+async fn donki_event(Path(id): Path<String>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    donki::event(&st.pool, &id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, format!("unknown event '{id}'")))
+}
+
+/* ---------- Здоровье ---------- */
+
+async fn health(State(st): State<AppState>) -> Json<Health> {
+    let status = if st.http.any_open() { "degraded" } else { "ok" };
+    Json(Health { status, now: Utc::now(), upstreams: st.http.breakers() })
+}
+
+/* ---------- Фоновые опросы ---------- */
+
+async fn jobs_list(State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let jobs = st.jobs.overview().await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({ "jobs": jobs })))
+}
+
+async fn job_runs(Path(name): Path<String>, Query(q): Query<HashMap<String,String>>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let limit = match q.get("limit") {
+        Some(v) => v.parse::<i64>().ok().filter(|n| (1..=500).contains(n))
+            .ok_or((StatusCode::BAD_REQUEST, "limit must be an integer in 1..=500".to_string()))?,
+        None => 50,
+    };
+    let runs = jobs::runs(&st.pool, &name, limit).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if runs.is_empty() && !st.jobs.contains(&name) {
+        return Err((StatusCode::NOT_FOUND, format!("unknown job '{name}'")));
+    }
+    Ok(Json(serde_json::json!({ "job": name, "runs": runs })))
+}
+
+/* ---------- Администрирование ---------- */
+
+/// С ADMIN_TOKEN — нужен заголовок `Authorization: Bearer <токен>`; без него — только запросы с localhost.
+async fn require_admin(State(st): State<AppState>, ConnectInfo(peer): ConnectInfo<SocketAddr>, req: Request, next: Next)
+-> Result<Response, (StatusCode, String)> {
+    match &st.settings().admin_token {
+        Some(token) => {
+            let given = req.headers().get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .unwrap_or("");
+            if !constant_time_eq(given.trim().as_bytes(), token.as_bytes()) {
+                return Err((StatusCode::UNAUTHORIZED, "admin token required".to_string()));
+            }
+        }
+        None if !peer.ip().is_loopback() => {
+            return Err((StatusCode::FORBIDDEN, "admin endpoints are local-only, set ADMIN_TOKEN for remote access".to_string()));
+        }
+        None => {}
+    }
+    Ok(next.run(req).await)
+}
+
+/// Сравнение без раннего выхода: время ответа не подсказывает совпавший префикс.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn admin_reload(State(st): State<AppState>)
+-> Result<Json<Reconciled>, (StatusCode, String)> {
+    st.reload().map(Json).map_err(|e| (StatusCode::BAD_REQUEST, format!("{e:#}")))
+}
+
+async fn admin_quota(State(st): State<AppState>) -> Json<Value> {
+    Json(st.http.nasa().overview())
+}
+
+/* ---------- Оповещения ---------- */
+
+async fn alerts_history(Query(q): Query<HashMap<String,String>>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let limit = match q.get("limit") {
+        Some(v) => v.parse::<i64>().ok().filter(|n| (1..=500).contains(n))
+            .ok_or((StatusCode::BAD_REQUEST, "limit must be an integer in 1..=500".to_string()))?,
+        None => 50,
+    };
+    let rule = q.get("rule").map(|r| r.trim()).filter(|r| !r.is_empty());
+    let items = alerts::history(&st.pool, rule, limit).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({ "alerts": items })))
+}
+
+/* ---------- Универсальная витрина space_cache ---------- */
+
+async fn space_latest(Path(src): Path<String>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let row = sqlx::query(
+        "SELECT fetched_at, payload FROM space_cache
+         WHERE source = $1 ORDER BY id DESC LIMIT 1"
+    ).bind(&src).fetch_optional(&st.pool).await
+     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(r) = row {
+        let fetched_at: DateTime<Utc> = r.get("fetched_at");
+        let payload: Value = r.get("payload");
+        return Ok(Json(serde_json::json!({ "source": src, "fetched_at": fetched_at, "payload": payload })));
+    }
+    Ok(Json(serde_json::json!({ "source": src, "message":"no data" })))
+}
+
+async fn space_refresh(Query(q): Query<HashMap<String,String>>, State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let settings = st.settings();
+    let names: Vec<String> = match q.get("src") {
+        Some(list) => list.split(',').map(|x| x.trim().to_lowercase()).filter(|x| !x.is_empty()).collect(),
+        None => settings.sources.names().into_iter().map(String::from).collect(),
+    };
+    let mut picked = Vec::with_capacity(names.len());
+    for n in &names {
+        let src = settings.sources.get(n).ok_or_else(|| (
+            StatusCode::BAD_REQUEST,
+            format!("unknown source '{n}', known: {}", settings.sources.names().join(",")),
+        ))?;
+        picked.push(src);
+    }
+
+    let mut done = Vec::new();
+    let mut errors = serde_json::Map::new();
+    for src in picked {
+        match run_feed(&st, &src).await {
+            Ok(_) => done.push(src.name()),
+            Err(e) => { errors.insert(src.name().to_string(), Value::String(e.to_string())); }
+        }
+    }
+    Ok(Json(serde_json::json!({ "refreshed": done, "errors": errors })))
+}
+
+async fn latest_from_cache(pool: &PgPool, src: &str) -> Value {
+    sqlx::query("SELECT fetched_at, payload FROM space_cache WHERE source=$1 ORDER BY id DESC LIMIT 1")
+        .bind(src)
+        .fetch_optional(pool).await.ok().flatten()
+        .map(|r| serde_json::json!({"at": r.get::<DateTime<Utc>,_>("fetched_at"), "payload": r.get::<Value,_>("payload")}))
+        .unwrap_or(serde_json::json!({}))
+}
+
+async fn space_summary(State(st): State<AppState>)
+-> Result<Json<Value>, (StatusCode, String)> {
+    let mut out = serde_json::Map::new();
+    for name in st.settings().sources.names() {
+        out.insert(name.to_string(), latest_from_cache(&st.pool, name).await);
+    }
+
+    let iss_last = sqlx::query("SELECT fetched_at,payload FROM iss_fetch_log ORDER BY id DESC LIMIT 1")
+        .fetch_optional(&st.pool).await.ok().flatten()
+        .map(|r| serde_json::json!({"at": r.get::<DateTime<Utc>,_>("fetched_at"), "payload": r.get::<Value,_>("payload")}))
+        .unwrap_or(serde_json::json!({}));
+
+    let osdr_count: i64 = sqlx::query("SELECT count(*) AS c FROM osdr_items")
+        .fetch_one(&st.pool).await.map(|r| r.get::<i64,_>("c")).unwrap_or(0);
+
+    out.insert("iss".into(), iss_last);
+    out.insert("osdr_count".into(), osdr_count.into());
+    Ok(Json(Value::Object(out)))
 }
 
 /* ---------- Вспомогательное ---------- */
@@ -498,47 +1860,396 @@ fn t_pick(v: &Value, keys: &[&str]) -> Option<DateTime<Utc>> {
     }
     None
 }
-async fn fetch_and_store_iss(pool: &PgPool, url: &str) -> anyhow::Result<()> {
-    let client = reqwest::Client::builder().timeout(Duration::from_secs(20)).build()?;
-    let resp = client.get(url).send().await?;
-    let json: Value = resp.json().await?;
-    sqlx::query("INSERT INTO iss_fetch_log (source_url, payload) VALUES ($1, $2)")
-        .bind(url).bind(json).execute(pool).await?;
+/// Разница между двумя JSON-документами в духе RFC 6902: add / remove / replace с JSON Pointer путями.
+/// Массивы сравниваются поэлементно по индексу; лишние элементы удаляются с конца, чтобы
+/// операции можно было применять по порядку.
+fn json_diff(old: &Value, new: &Value, path: &str, out: &mut Vec<Value>) {
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (k, va) in a {
+                let p = format!("{path}/{}", k.replace('~', "~0").replace('/', "~1"));
+                match b.get(k) {
+                    Some(vb) => json_diff(va, vb, &p, out),
+                    None => out.push(serde_json::json!({ "op": "remove", "path": p, "old": va })),
+                }
+            }
+            for (k, vb) in b {
+                if !a.contains_key(k) {
+                    let p = format!("{path}/{}", k.replace('~', "~0").replace('/', "~1"));
+                    out.push(serde_json::json!({ "op": "add", "path": p, "value": vb }));
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for (i, (va, vb)) in a.iter().zip(b).enumerate() {
+                json_diff(va, vb, &format!("{path}/{i}"), out);
+            }
+            for (i, vb) in b.iter().enumerate().skip(a.len()) {
+                out.push(serde_json::json!({ "op": "add", "path": format!("{path}/{i}"), "value": vb }));
+            }
+            for (i, va) in a.iter().enumerate().skip(b.len()).rev() {
+                out.push(serde_json::json!({ "op": "remove", "path": format!("{path}/{i}"), "old": va }));
+            }
+        }
+        _ if old != new => out.push(serde_json::json!({ "op": "replace", "path": path, "old": old, "value": new })),
+        _ => {}
+    }
+}
+fn hex_encode(b: &[u8]) -> String {
+    b.iter().map(|x| format!("{x:02x}")).collect()
+}
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) { return None; }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+/// Источник положения МКС. Ответы всех источников приводятся к `IssPosition`.
+#[derive(Clone, Debug)]
+enum IssProvider {
+    WhereTheIss(String),
+    OpenNotify(String),
+    /// расчёт по последнему TLE из iss_tle
+    Tle,
+}
+
+impl IssProvider {
+    fn parse_list(list: &str, where_iss_url: &str, open_notify_url: &str) -> anyhow::Result<Vec<Self>> {
+        let providers = list.split(',').map(|x| x.trim().to_lowercase()).filter(|x| !x.is_empty())
+            .map(|name| match name.as_str() {
+                "wheretheiss" => Ok(Self::WhereTheIss(where_iss_url.to_string())),
+                "open-notify" | "opennotify" => Ok(Self::OpenNotify(open_notify_url.to_string())),
+                "tle" => Ok(Self::Tle),
+                _ => Err(anyhow::anyhow!("ISS_PROVIDERS: unknown provider '{name}' (expected wheretheiss, open-notify, tle)")),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if providers.is_empty() {
+            anyhow::bail!("ISS_PROVIDERS must list at least one provider");
+        }
+        Ok(providers)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::WhereTheIss(_) => "wheretheiss",
+            Self::OpenNotify(_) => "open-notify",
+            Self::Tle => "tle",
+        }
+    }
+
+    /// (source_url, исходный payload, разобранное положение)
+    async fn fetch(&self, pool: &PgPool, http: &Http, cfg: &config::SourceConfig) -> anyhow::Result<(String, Value, IssPosition)> {
+        match self {
+            Self::WhereTheIss(url) => {
+                let json = fetch_iss_json(http, cfg, url).await?;
+                let pos = IssPosition::from_payload(&json)
+                    .ok_or_else(|| anyhow::anyhow!("payload without coordinates"))?;
+                Ok((url.clone(), json, pos))
+            }
+            Self::OpenNotify(url) => {
+                // {"message":"success","timestamp":..., "iss_position":{"latitude":"..","longitude":".."}}
+                let json = fetch_iss_json(http, cfg, url).await?;
+                let p = &json["iss_position"];
+                let pos = IssPosition {
+                    latitude: num(&p["latitude"]).ok_or_else(|| anyhow::anyhow!("payload without latitude"))?,
+                    longitude: num(&p["longitude"]).ok_or_else(|| anyhow::anyhow!("payload without longitude"))?,
+                    altitude_km: None,
+                    velocity_kmh: None,
+                    visibility: None,
+                    footprint_km: None,
+                    timestamp: t_pick(&json, &["timestamp"]),
+                };
+                Ok((url.clone(), json, pos))
+            }
+            Self::Tle => {
+                let (tle, model) = latest_sgp4(pool).await?
+                    .ok_or_else(|| anyhow::anyhow!("no TLE loaded"))?;
+                let pos = predict_position(&model, Utc::now())?;
+                let json = serde_json::json!({ "position": &pos, "tle": tle_json(&tle, Utc::now()) });
+                Ok((format!("sgp4:{}", tle.epoch.to_rfc3339()), json, pos))
+            }
+        }
+    }
+}
+
+async fn fetch_iss_json(http: &Http, cfg: &config::SourceConfig, url: &str) -> anyhow::Result<Value> {
+    let resp = http.send(cfg.get(&http.client(), url)).await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("status {}", resp.status());
+    }
+    Ok(resp.json().await?)
+}
+
+/// Опрашивает источники по порядку до первого успешного ответа.
+async fn fetch_and_store_iss(st: &AppState) -> anyhow::Result<()> {
+    let mut failures = Vec::new();
+    let mut answered = None;
+    let settings = st.settings();
+    for provider in &settings.iss_providers {
+        match provider.fetch(&st.pool, &st.http, &settings.config.iss).await {
+            Ok(r) => { answered = Some(r); break; }
+            Err(e) => {
+                warn!("iss provider {} failed: {e}", provider.name());
+                failures.push(format!("{}: {e}", provider.name()));
+            }
+        }
+    }
+    let Some((source_url, json, pos)) = answered else {
+        anyhow::bail!("all ISS providers failed: {}", failures.join("; "));
+    };
+
+    let row = sqlx::query(&format!(
+        "INSERT INTO iss_fetch_log (source_url, payload,
+             latitude, longitude, altitude_km, velocity_kmh, visibility, footprint_km, position_at, parsed_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now())
+         RETURNING {ISS_COLUMNS}"
+    )).bind(&source_url).bind(&json)
+     .bind(pos.latitude).bind(pos.longitude)
+     .bind(pos.altitude_km).bind(pos.velocity_kmh)
+     .bind(pos.visibility).bind(pos.footprint_km)
+     .bind(pos.timestamp)
+     .fetch_one(&st.pool).await?;
+    if let Some(days) = settings.config.iss.retention_days {
+        sqlx::query("DELETE FROM iss_fetch_log WHERE fetched_at < now() - make_interval(days => $1)")
+            .bind(days as i32).execute(&st.pool).await?;
+    }
+    // нет подписчиков — не ошибка
+    let _ = st.iss_tx.send(Arc::new(iss_row_json(&row)));
     Ok(())
 }
-async fn fetch_and_store_osdr(st: &AppState) -> anyhow::Result<usize> {
-    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
-    let resp = client.get(&st.nasa_url).send().await?;
+#[derive(Serialize, Default)]
+struct OsdrSyncStats {
+    written: usize,
+    shapes: BTreeMap<&'static str, usize>,
+}
+
+async fn fetch_and_store_osdr(st: &AppState) -> anyhow::Result<OsdrSyncStats> {
+    let cfg = &st.settings().config.osdr;
+    let Some((resp, validators)) = st.http.send_conditional(cfg.request(&st.http.client()), cfg.nasa()).await? else {
+        // список датасетов не менялся
+        return Ok(OsdrSyncStats::default());
+    };
     if !resp.status().is_success() {
         anyhow::bail!("OSDR request status {}", resp.status());
     }
     let json: Value = resp.json().await?;
-    let items = if let Some(a) = json.as_array() { a.clone() }
-        else if let Some(v) = json.get("items").and_then(|x| x.as_array()) { v.clone() }
-        else if let Some(v) = json.get("results").and_then(|x| x.as_array()) { v.clone() }
-        else { vec![json.clone()] };
-
-    let mut written = 0usize;
-    for item in items {
-        let id = s_pick(&item, &["dataset_id","id","uuid","studyId","accession","osdr_id"]);
-        let title = s_pick(&item, &["title","name","label"]);
-        let status = s_pick(&item, &["status","state","lifecycle"]);
-        let updated = t_pick(&item, &["updated","updated_at","modified","lastUpdated","timestamp"]);
-        if let Some(ds) = id.clone() {
-            sqlx::query(
-                "INSERT INTO osdr_items(dataset_id, title, status, updated_at, raw)
-                 VALUES($1,$2,$3,$4,$5)
-                 ON CONFLICT (dataset_id) DO UPDATE
-                 SET title=EXCLUDED.title, status=EXCLUDED.status,
-                     updated_at=EXCLUDED.updated_at, raw=EXCLUDED.raw"
-            ).bind(ds).bind(title).bind(status).bind(updated).bind(item).execute(&st.pool).await?;
-        } else {
-            sqlx::query(
-                "INSERT INTO osdr_items(dataset_id, title, status, updated_at, raw)
-                 VALUES($1,$2,$3,$4,$5)"
-            ).bind::<Option<String>>(None).bind(title).bind(status).bind(updated).bind(item).execute(&st.pool).await?;
+    let (shape, items) = split_osdr_response(json);
+
+    let mut stats = OsdrSyncStats::default();
+    for (key, item) in items {
+        upsert_osdr_item(&st.pool, key, item).await?;
+        stats.written += 1;
+        *stats.shapes.entry(shape).or_default() += 1;
+    }
+    st.http.remember(validators).await?;
+    Ok(stats)
+}
+
+/// Раскладывает ответ OSDR на элементы: (форма ответа, [(ключ-accession если есть, элемент)]).
+fn split_osdr_response(json: Value) -> (&'static str, Vec<(Option<String>, Value)>) {
+    let unkeyed = |a: &Vec<Value>| a.iter().map(|v| (None, v.clone())).collect();
+    if let Some(a) = json.as_array() { ("array", unkeyed(a)) }
+    else if let Some(v) = json.get("items").and_then(|x| x.as_array()) { ("items", unkeyed(v)) }
+    else if let Some(v) = json.get("results").and_then(|x| x.as_array()) { ("results", unkeyed(v)) }
+    else if looks_osdr_dict(&json) {
+        let m = json.as_object().cloned().unwrap_or_default();
+        ("dict", m.into_iter().map(|(k, v)| (Some(k), v)).collect())
+    }
+    else { ("single", vec![(None, json)]) }
+}
+
+/// "OSD-123".
+fn is_osdr_accession(id: &str) -> bool {
+    id.strip_prefix("OSD-").is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Перезапрашивает одну запись по accession и пишет её тем же путём, что и полная синхронизация.
+/// `Ok(false)` — апстрим такой записи не знает. `dataset_id` проверен `is_osdr_accession`.
+async fn fetch_and_store_osdr_one(st: &AppState, dataset_id: &str) -> anyhow::Result<bool> {
+    let settings = st.settings();
+    let url = settings.nasa_item_url.replace("{id}", dataset_id);
+    let resp = st.http.send(settings.config.osdr.get(&st.http.client(), &url)).await?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+    if !resp.status().is_success() {
+        anyhow::bail!("OSDR request status {}", resp.status());
+    }
+    let json: Value = resp.json().await?;
+    // и одиночный объект принимаем, только если это запрошенная запись
+    let (_, items) = split_osdr_response(json);
+    let item = items.into_iter().find(|(k, v)| {
+        k.as_deref() == Some(dataset_id)
+            || s_pick(v, &["dataset_id","id","uuid","studyId","accession","osdr_id"]).as_deref() == Some(dataset_id)
+    });
+    let Some((_, item)) = item else { return Ok(false) };
+    upsert_osdr_item(&st.pool, Some(dataset_id.to_string()), item).await?;
+    Ok(true)
+}
+
+/// Словарь вида {"OSD-1": {...}, "OSD-2": {...}}: все значения — объекты,
+/// и хотя бы один ключ похож на accession или значение несёт REST_URL.
+fn looks_osdr_dict(v: &Value) -> bool {
+    let Some(obj) = v.as_object() else { return false };
+    !obj.is_empty()
+        && obj.values().all(|x| x.is_object())
+        && obj.iter().any(|(k, x)| {
+            k.starts_with("OSD-") || x.get("REST_URL").is_some() || x.get("rest_url").is_some()
+        })
+}
+
+async fn upsert_osdr_item(pool: &PgPool, key: Option<String>, item: Value) -> anyhow::Result<()> {
+    let id = key.or_else(|| s_pick(&item, &["dataset_id","id","uuid","studyId","accession","osdr_id"]));
+    let title = s_pick(&item, &["title","name","label"]).or_else(|| {
+        // запасной вариант: последний сегмент REST_URL как подпись
+        s_pick(&item, &["REST_URL","rest_url"])
+            .and_then(|u| u.trim_end_matches('/').rsplit('/').next().map(str::to_string))
+            .filter(|s| !s.is_empty())
+    });
+    let status = s_pick(&item, &["status","state","lifecycle"]);
+    let updated = t_pick(&item, &["updated","updated_at","modified","lastUpdated","timestamp"]);
+    if let Some(ds) = id {
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO osdr_items(dataset_id, title, status, updated_at, raw)
+             VALUES($1,$2,$3,$4,$5)
+             ON CONFLICT (dataset_id) WHERE dataset_id IS NOT NULL DO UPDATE
+             SET title=EXCLUDED.title, status=EXCLUDED.status,
+                 updated_at=EXCLUDED.updated_at, raw=EXCLUDED.raw
+             WHERE (osdr_items.title, osdr_items.status, osdr_items.updated_at, osdr_items.raw)
+                   IS DISTINCT FROM (EXCLUDED.title, EXCLUDED.status, EXCLUDED.updated_at, EXCLUDED.raw)"
+        ).bind(&ds).bind(&title).bind(&status).bind(updated).bind(&item).execute(&mut *tx).await?;
+        // новая ревизия, только если хэш raw отличается от последней записанной
+        sqlx::query(
+            "WITH h AS (SELECT encode(sha256(convert_to($2::jsonb::text, 'UTF8')), 'hex') AS hash),
+                  last AS (SELECT version, content_hash FROM osdr_item_versions
+                           WHERE dataset_id = $1 ORDER BY version DESC LIMIT 1)
+             INSERT INTO osdr_item_versions(dataset_id, version, content_hash, title, status, updated_at, raw)
+             SELECT $1, COALESCE((SELECT version FROM last), 0) + 1, h.hash, $3, $4, $5, $2
+             FROM h
+             WHERE h.hash IS DISTINCT FROM (SELECT content_hash FROM last)
+             ON CONFLICT (dataset_id, version) DO NOTHING"
+        ).bind(&ds).bind(&item).bind(title).bind(status).bind(updated).execute(&mut *tx).await?;
+        tx.commit().await?;
+    } else {
+        sqlx::query(
+            "INSERT INTO osdr_items(dataset_id, title, status, updated_at, raw)
+             VALUES($1,$2,$3,$4,$5)"
+        ).bind::<Option<String>>(None).bind(title).bind(status).bind(updated).bind(item).execute(pool).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn diff(old: Value, new: Value) -> Vec<Value> {
+        let mut out = Vec::new();
+        json_diff(&old, &new, "", &mut out);
+        out
+    }
+
+    #[test]
+    fn json_diff_equal_documents() {
+        let doc = json!({ "a": [1, { "b": null }], "c": "x" });
+        assert!(diff(doc.clone(), doc).is_empty());
+    }
+
+    #[test]
+    fn json_diff_nested_objects() {
+        let ops = diff(
+            json!({ "study": { "title": "Old", "factors": { "space": true } }, "gone": 1 }),
+            json!({ "study": { "title": "New", "factors": { "space": true, "dose": "2Gy" } }, "a/b~c": 2 }),
+        );
+        assert_eq!(ops, [
+            json!({ "op": "remove", "path": "/gone", "old": 1 }),
+            json!({ "op": "add", "path": "/study/factors/dose", "value": "2Gy" }),
+            json!({ "op": "replace", "path": "/study/title", "old": "Old", "value": "New" }),
+            // ключ с '/' и '~' экранируется по RFC 6901
+            json!({ "op": "add", "path": "/a~1b~0c", "value": 2 }),
+        ]);
+    }
+
+    #[test]
+    fn json_diff_arrays() {
+        assert_eq!(diff(json!({ "x": [1, 2] }), json!({ "x": [1, 3, 4] })), [
+            json!({ "op": "replace", "path": "/x/1", "old": 2, "value": 3 }),
+            json!({ "op": "add", "path": "/x/2", "value": 4 }),
+        ]);
+        // удаление с конца: индексы не съезжают
+        assert_eq!(diff(json!([1, 2, 3, 4]), json!([1])), [
+            json!({ "op": "remove", "path": "/3", "old": 4 }),
+            json!({ "op": "remove", "path": "/2", "old": 3 }),
+            json!({ "op": "remove", "path": "/1", "old": 2 }),
+        ]);
+        assert_eq!(diff(json!([{ "id": 1 }]), json!([{ "id": 2 }])), [
+            json!({ "op": "replace", "path": "/0/id", "old": 1, "value": 2 }),
+        ]);
+    }
+
+    #[test]
+    fn json_diff_type_change_replaces_whole_value() {
+        assert_eq!(diff(json!({ "a": [1] }), json!({ "a": { "0": 1 } })), [
+            json!({ "op": "replace", "path": "/a", "old": [1], "value": { "0": 1 } }),
+        ]);
+        assert_eq!(diff(json!(1), json!("1")), [json!({ "op": "replace", "path": "", "old": 1, "value": "1" })]);
+    }
+
+    /// Трасса круговой орбиты с наклонением `incl_deg` над вращающейся Землёй, шаг 60 с.
+    fn circular_track(incl_deg: f64, samples: i64) -> (Vec<TrackPoint>, f64) {
+        let (h, r) = (420.0, geo::EARTH_RADIUS_KM);
+        let n = (398_600.441_8 / (r + h).powi(3)).sqrt(); // рад/с
+        let v_kmh = n * (r + h) * 3600.0;
+        let i = incl_deg.to_radians();
+        let pts = (0..samples).map(|k| {
+            let t = k as f64 * 60.0;
+            let u = n * t;
+            let lat = (i.sin() * u.sin()).asin().to_degrees();
+            let lon = (i.cos() * u.sin()).atan2(u.cos()).to_degrees() - geo::EARTH_ROTATION_DEG_PER_S * t;
+            TrackPoint {
+                t: DateTime::from_timestamp(1_700_000_000 + k * 60, 0).unwrap(),
+                lat, lon: (lon + 540.0).rem_euclid(360.0) - 180.0,
+                altitude_km: Some(h), velocity_kmh: Some(v_kmh),
+            }
+        }).collect();
+        (pts, v_kmh)
+    }
+
+    #[test]
+    fn velocity_check_uses_inertial_speed() {
+        for incl in [0.0, 51.6, 98.0] {
+            let (pts, v) = circular_track(incl, 40);
+            let check = trend_stats("40".into(), &pts).velocity_check.unwrap();
+            assert!(check.consistent, "incl {incl}: {}%", check.deviation_pct);
+            assert!(check.deviation_pct < 0.5, "incl {incl}: {}%", check.deviation_pct);
+            assert!((check.reported_mean_kmh - v).abs() < 1e-9);
         }
-        written += 1;
+        // по экватору на восток наземная скорость меньше орбитальной на вращение Земли
+        let (pts, v) = circular_track(0.0, 10);
+        let ground = trend_stats("10".into(), &pts).speed_kmh.mean.unwrap() * (geo::EARTH_RADIUS_KM + 420.0) / geo::EARTH_RADIUS_KM;
+        assert!(ground < v * 0.95);
+    }
+
+    #[test]
+    fn trend_window_parse() {
+        assert!(matches!(TrendWindow::parse("2"), Some(TrendWindow::Samples(2))));
+        assert!(matches!(TrendWindow::parse(" 30m "), Some(TrendWindow::Span(d)) if d == chrono::Duration::minutes(30)));
+        assert!(matches!(TrendWindow::parse("7d"), Some(TrendWindow::Span(d)) if d == chrono::Duration::days(7)));
+        assert!(TrendWindow::parse("1").is_none());
+        assert!(TrendWindow::parse("8d").is_none());
+    }
+
+    #[test]
+    fn trend_window_rejects_garbage() {
+        // многобайтная единица не должна ронять обработчик
+        assert!(TrendWindow::parse("5м").is_none());
+        assert!(TrendWindow::parse("м").is_none());
+        assert!(TrendWindow::parse("").is_none());
+        assert!(TrendWindow::parse("h").is_none());
+        assert!(TrendWindow::parse("0h").is_none());
+        assert!(TrendWindow::parse("-5m").is_none());
+        assert!(TrendWindow::parse("5w").is_none());
+        assert!(TrendWindow::parse("9223372036854775807d").is_none());
+        assert!(TrendWindow::parse("99999999999999999999s").is_none());
     }
-    Ok(written)
 }
@@ -0,0 +1,241 @@
+//! Околоземные объекты из ленты NeoWs: объекты и их сближения в отдельных таблицах.
+//! Одно и то же сближение приходит в нескольких соседних выборках — строки обновляются на месте.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+
+#[derive(Debug)]
+struct NeoObject {
+    id: String,
+    name: String,
+    jpl_url: Option<String>,
+    absolute_magnitude_h: Option<f64>,
+    diameter_min_km: Option<f64>,
+    diameter_max_km: Option<f64>,
+    hazardous: bool,
+    sentry: bool,
+    approaches: Vec<CloseApproach>,
+}
+
+#[derive(Debug)]
+struct CloseApproach {
+    approach_at: DateTime<Utc>,
+    orbiting_body: String,
+    miss_km: Option<f64>,
+    miss_lunar: Option<f64>,
+    miss_au: Option<f64>,
+    velocity_kps: Option<f64>,
+}
+
+/// NeoWs отдаёт числа то строками, то числами.
+fn f(v: &Value) -> Option<f64> {
+    v.as_f64().or_else(|| v.as_str()?.trim().parse().ok())
+}
+
+fn parse_object(v: &Value) -> Option<NeoObject> {
+    let id = v.get("neo_reference_id").or_else(|| v.get("id"))?.as_str()?.to_string();
+    let km = &v["estimated_diameter"]["kilometers"];
+    let approaches = v["close_approach_data"].as_array().map(|a| a.iter().filter_map(parse_approach).collect());
+    Some(NeoObject {
+        name: v["name"].as_str().unwrap_or(&id).trim().to_string(),
+        id,
+        jpl_url: v["nasa_jpl_url"].as_str().map(str::to_string),
+        absolute_magnitude_h: f(&v["absolute_magnitude_h"]),
+        diameter_min_km: f(&km["estimated_diameter_min"]),
+        diameter_max_km: f(&km["estimated_diameter_max"]),
+        hazardous: v["is_potentially_hazardous_asteroid"].as_bool().unwrap_or(false),
+        sentry: v["is_sentry_object"].as_bool().unwrap_or(false),
+        approaches: approaches.unwrap_or_default(),
+    })
+}
+
+fn parse_approach(v: &Value) -> Option<CloseApproach> {
+    // epoch_date_close_approach — миллисекунды; точнее, чем строковая дата
+    let approach_at = DateTime::from_timestamp_millis(v["epoch_date_close_approach"].as_i64()?)?;
+    let miss = &v["miss_distance"];
+    Some(CloseApproach {
+        approach_at,
+        orbiting_body: v["orbiting_body"].as_str().unwrap_or("Earth").to_string(),
+        miss_km: f(&miss["kilometers"]),
+        miss_lunar: f(&miss["lunar"]),
+        miss_au: f(&miss["astronomical"]),
+        velocity_kps: f(&v["relative_velocity"]["kilometers_per_second"]),
+    })
+}
+
+/// Объекты из ответа /neo/rest/v1/feed (`near_earth_objects` по датам); один объект может
+/// встречаться под несколькими датами.
+fn parse_feed(feed: &Value) -> Vec<NeoObject> {
+    let mut out: Vec<NeoObject> = Vec::new();
+    let by_date = feed["near_earth_objects"].as_object().into_iter().flat_map(|m| m.values());
+    for obj in by_date.filter_map(Value::as_array).flatten().filter_map(parse_object) {
+        match out.iter_mut().find(|o| o.id == obj.id) {
+            Some(known) => known.approaches.extend(obj.approaches),
+            None => out.push(obj),
+        }
+    }
+    out
+}
+
+/// Раскладывает ленту по таблицам; возвращает число новых или изменившихся строк.
+pub async fn store_feed(pool: &PgPool, feed: &Value) -> anyhow::Result<u64> {
+    let mut written = 0;
+    let mut tx = pool.begin().await?;
+    for o in parse_feed(feed) {
+        written += sqlx::query(
+            "INSERT INTO neo_objects(id, name, nasa_jpl_url, absolute_magnitude_h, diameter_min_km, diameter_max_km,
+                                     hazardous, sentry)
+             VALUES ($1,$2,$3,$4,$5,$6,$7,$8)
+             ON CONFLICT (id) DO UPDATE SET
+                name = EXCLUDED.name, nasa_jpl_url = EXCLUDED.nasa_jpl_url,
+                absolute_magnitude_h = EXCLUDED.absolute_magnitude_h,
+                diameter_min_km = EXCLUDED.diameter_min_km, diameter_max_km = EXCLUDED.diameter_max_km,
+                hazardous = EXCLUDED.hazardous, sentry = EXCLUDED.sentry, updated_at = now()
+             WHERE (neo_objects.name, neo_objects.nasa_jpl_url, neo_objects.absolute_magnitude_h,
+                    neo_objects.diameter_min_km, neo_objects.diameter_max_km, neo_objects.hazardous, neo_objects.sentry)
+                IS DISTINCT FROM
+                   (EXCLUDED.name, EXCLUDED.nasa_jpl_url, EXCLUDED.absolute_magnitude_h,
+                    EXCLUDED.diameter_min_km, EXCLUDED.diameter_max_km, EXCLUDED.hazardous, EXCLUDED.sentry)"
+        ).bind(&o.id).bind(&o.name).bind(&o.jpl_url).bind(o.absolute_magnitude_h)
+         .bind(o.diameter_min_km).bind(o.diameter_max_km).bind(o.hazardous).bind(o.sentry)
+         .execute(&mut *tx).await?.rows_affected();
+
+        for a in &o.approaches {
+            // уточнение орбиты меняет дистанцию и скорость — перезаписываем
+            written += sqlx::query(
+                "INSERT INTO neo_close_approaches(neo_id, approach_at, orbiting_body, miss_km, miss_lunar, miss_au, velocity_kps)
+                 VALUES ($1,$2,$3,$4,$5,$6,$7)
+                 ON CONFLICT (neo_id, approach_at, orbiting_body) DO UPDATE SET
+                    miss_km = EXCLUDED.miss_km, miss_lunar = EXCLUDED.miss_lunar, miss_au = EXCLUDED.miss_au,
+                    velocity_kps = EXCLUDED.velocity_kps, updated_at = now()
+                 WHERE (neo_close_approaches.miss_km, neo_close_approaches.miss_lunar,
+                        neo_close_approaches.miss_au, neo_close_approaches.velocity_kps)
+                    IS DISTINCT FROM
+                       (EXCLUDED.miss_km, EXCLUDED.miss_lunar, EXCLUDED.miss_au, EXCLUDED.velocity_kps)"
+            ).bind(&o.id).bind(a.approach_at).bind(&a.orbiting_body).bind(a.miss_km)
+             .bind(a.miss_lunar).bind(a.miss_au).bind(a.velocity_kps)
+             .execute(&mut *tx).await?.rows_affected();
+        }
+    }
+    tx.commit().await?;
+    Ok(written)
+}
+
+/* ---------- Запросы ---------- */
+
+#[derive(Debug, Clone, Copy)]
+pub enum ApproachSort { Time, Miss, Velocity, Diameter }
+
+impl ApproachSort {
+    /// "approach_at", "miss_km", "velocity_kps", "diameter_km"; минус впереди — по убыванию.
+    pub fn parse(s: &str) -> Option<(Self, bool)> {
+        let (desc, name) = match s.strip_prefix('-') { Some(n) => (true, n), None => (false, s) };
+        let key = match name {
+            "approach_at" => Self::Time,
+            "miss_km" => Self::Miss,
+            "velocity_kps" => Self::Velocity,
+            "diameter_km" => Self::Diameter,
+            _ => return None,
+        };
+        Some((key, desc))
+    }
+
+    fn expr(self) -> &'static str {
+        match self {
+            Self::Time => "a.approach_at",
+            Self::Miss => "a.miss_km",
+            Self::Velocity => "a.velocity_kps",
+            Self::Diameter => "o.diameter_max_km",
+        }
+    }
+}
+
+pub struct ApproachFilter {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub hazardous: Option<bool>,
+    pub max_miss_km: Option<f64>,
+    pub sort: ApproachSort,
+    pub desc: bool,
+    pub limit: i64,
+}
+
+const APPROACH_COLUMNS: &str = "a.neo_id, o.name, o.hazardous, o.diameter_min_km, o.diameter_max_km,
+    a.approach_at, a.orbiting_body, a.miss_km, a.miss_lunar, a.miss_au, a.velocity_kps";
+
+fn approach_json(r: &sqlx::postgres::PgRow) -> Value {
+    serde_json::json!({
+        "neo_id": r.get::<String,_>("neo_id"),
+        "name": r.get::<String,_>("name"),
+        "hazardous": r.get::<bool,_>("hazardous"),
+        "diameter_km": {
+            "min": r.get::<Option<f64>,_>("diameter_min_km"),
+            "max": r.get::<Option<f64>,_>("diameter_max_km"),
+        },
+        "approach_at": r.get::<DateTime<Utc>,_>("approach_at"),
+        "orbiting_body": r.get::<String,_>("orbiting_body"),
+        "miss_distance": {
+            "km": r.get::<Option<f64>,_>("miss_km"),
+            "lunar": r.get::<Option<f64>,_>("miss_lunar"),
+            "au": r.get::<Option<f64>,_>("miss_au"),
+        },
+        "velocity_kps": r.get::<Option<f64>,_>("velocity_kps"),
+    })
+}
+
+/// Сближения в окне по фильтру и общее число подходящих (без учёта limit).
+pub async fn approaches(pool: &PgPool, q: &ApproachFilter) -> anyhow::Result<(i64, Vec<Value>)> {
+    let dir = if q.desc { "DESC" } else { "ASC" };
+    let rows = sqlx::query(&format!(
+        "SELECT {APPROACH_COLUMNS}, count(*) OVER () AS total
+         FROM neo_close_approaches a JOIN neo_objects o ON o.id = a.neo_id
+         WHERE a.approach_at >= $1 AND a.approach_at < $2
+           AND ($3::boolean IS NULL OR o.hazardous = $3)
+           AND ($4::double precision IS NULL OR a.miss_km <= $4)
+         ORDER BY {} {dir} NULLS LAST, a.approach_at, a.neo_id
+         LIMIT $5",
+        q.sort.expr()
+    )).bind(q.from).bind(q.to).bind(q.hazardous).bind(q.max_miss_km).bind(q.limit)
+      .fetch_all(pool).await?;
+    let total = rows.first().map_or(0, |r| r.get::<i64,_>("total"));
+    Ok((total, rows.iter().map(approach_json).collect()))
+}
+
+/// Объект со всеми известными сближениями; `None` — такого нет.
+pub async fn object(pool: &PgPool, id: &str) -> anyhow::Result<Option<Value>> {
+    let Some(o) = sqlx::query(
+        "SELECT id, name, nasa_jpl_url, absolute_magnitude_h, diameter_min_km, diameter_max_km,
+                hazardous, sentry, first_seen_at, updated_at
+         FROM neo_objects WHERE id = $1"
+    ).bind(id).fetch_optional(pool).await? else {
+        return Ok(None);
+    };
+    let rows = sqlx::query(&format!(
+        "SELECT {APPROACH_COLUMNS}
+         FROM neo_close_approaches a JOIN neo_objects o ON o.id = a.neo_id
+         WHERE a.neo_id = $1 ORDER BY a.approach_at"
+    )).bind(id).fetch_all(pool).await?;
+    let approaches: Vec<Value> = rows.iter().map(approach_json).map(|mut a| {
+        // в ответе объекта поля объекта не повторяем
+        if let Some(m) = a.as_object_mut() {
+            for k in ["neo_id", "name", "hazardous", "diameter_km"] { m.remove(k); }
+        }
+        a
+    }).collect();
+    Ok(Some(serde_json::json!({
+        "id": o.get::<String,_>("id"),
+        "name": o.get::<String,_>("name"),
+        "nasa_jpl_url": o.get::<Option<String>,_>("nasa_jpl_url"),
+        "absolute_magnitude_h": o.get::<Option<f64>,_>("absolute_magnitude_h"),
+        "diameter_km": {
+            "min": o.get::<Option<f64>,_>("diameter_min_km"),
+            "max": o.get::<Option<f64>,_>("diameter_max_km"),
+        },
+        "hazardous": o.get::<bool,_>("hazardous"),
+        "sentry": o.get::<bool,_>("sentry"),
+        "first_seen_at": o.get::<DateTime<Utc>,_>("first_seen_at"),
+        "updated_at": o.get::<DateTime<Utc>,_>("updated_at"),
+        "close_approaches": approaches,
+    })))
+}
@@ -0,0 +1,292 @@
+//! Пролёты МКС над наземным наблюдателем: углы места/азимуты, положение Солнца и освещённость.
+
+use chrono::{DateTime, Duration, Timelike, Utc};
+use serde::Serialize;
+
+use crate::sgp4::{self, Sgp4, Sgp4Error};
+
+const WGS84_A_KM: f64 = 6378.137;
+const WGS84_F: f64 = 1.0 / 298.257223563;
+const EARTH_MEAN_RADIUS_KM: f64 = 6371.0;
+const AU_KM: f64 = 149_597_870.7;
+
+/// Шаг грубого поиска восхода; пролёт МКС короче 20 с над горизонтом не бывает виден.
+const SCAN_STEP_SEC: i64 = 20;
+/// Насколько можно заглянуть за конец окна, чтобы найти заход начатого пролёта
+/// (пролёт МКС длится не дольше ~12 минут).
+const MAX_OVERRUN_MIN: i64 = 20;
+/// Пролёты ниже этого максимального угла места по умолчанию не показываем.
+pub const DEFAULT_MIN_ELEVATION_DEG: f64 = 10.0;
+/// Солнце ниже этого угла — у наблюдателя гражданские сумерки или темнее.
+const SUN_DARK_ELEVATION_DEG: f64 = -6.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Observer {
+    pub lat_deg: f64,
+    pub lon_deg: f64,
+    pub alt_km: f64,
+}
+
+impl Observer {
+    fn ecef(&self) -> [f64; 3] {
+        let (lat, lon) = (self.lat_deg.to_radians(), self.lon_deg.to_radians());
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let n = WGS84_A_KM / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        [
+            (n + self.alt_km) * lat.cos() * lon.cos(),
+            (n + self.alt_km) * lat.cos() * lon.sin(),
+            (n * (1.0 - e2) + self.alt_km) * lat.sin(),
+        ]
+    }
+
+    /// Азимут (от севера по часовой) и угол места точки в ECEF, градусы.
+    fn look_angles(&self, target_ecef: [f64; 3]) -> (f64, f64) {
+        let o = self.ecef();
+        let d = [target_ecef[0] - o[0], target_ecef[1] - o[1], target_ecef[2] - o[2]];
+        let (lat, lon) = (self.lat_deg.to_radians(), self.lon_deg.to_radians());
+        let (slat, clat, slon, clon) = (lat.sin(), lat.cos(), lon.sin(), lon.cos());
+        // SEZ -> ENU
+        let east = -slon * d[0] + clon * d[1];
+        let north = -slat * clon * d[0] - slat * slon * d[1] + clat * d[2];
+        let up = clat * clon * d[0] + clat * slon * d[1] + slat * d[2];
+        let range = (east * east + north * north + up * up).sqrt();
+        let az = east.atan2(north).to_degrees().rem_euclid(360.0);
+        let el = (up / range).asin().to_degrees();
+        (az, el)
+    }
+}
+
+/// Направление на Солнце в инерциальной системе даты (малоточная формула Astronomical Almanac,
+/// ~0.01°), единичный вектор и расстояние в км.
+pub fn sun_eci(t: DateTime<Utc>) -> ([f64; 3], f64) {
+    let n = sgp4::julian_date(t) - 2_451_545.0;
+    let l = (280.460 + 0.985_647_4 * n).rem_euclid(360.0);
+    let g = (357.528 + 0.985_600_3 * n).rem_euclid(360.0).to_radians();
+    let lambda = (l + 1.915 * g.sin() + 0.020 * (2.0 * g).sin()).to_radians();
+    let eps = (23.439 - 0.000_000_4 * n).to_radians();
+    let r_au = 1.000_14 - 0.016_71 * g.cos() - 0.000_14 * (2.0 * g).cos();
+    (
+        [lambda.cos(), eps.cos() * lambda.sin(), eps.sin() * lambda.sin()],
+        r_au * AU_KM,
+    )
+}
+
+/// Освещён ли спутник: цилиндрическая модель тени Земли.
+pub fn is_sunlit(r_eci: [f64; 3], sun_dir: [f64; 3]) -> bool {
+    let dot = r_eci[0] * sun_dir[0] + r_eci[1] * sun_dir[1] + r_eci[2] * sun_dir[2];
+    if dot > 0.0 {
+        return true;
+    }
+    let perp2 = r_eci.iter().map(|x| x * x).sum::<f64>() - dot * dot;
+    perp2.sqrt() > EARTH_MEAN_RADIUS_KM
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PassPoint {
+    pub t: DateTime<Utc>,
+    pub azimuth_deg: f64,
+    pub elevation_deg: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Pass {
+    pub rise: PassPoint,
+    pub culmination: PassPoint,
+    pub set: PassPoint,
+    pub duration_sec: i64,
+    /// "visible" — МКС освещена, а у наблюдателя темно; "daylight" — небо светлое;
+    /// "eclipsed" — темно, но МКС в тени Земли всё время пролёта.
+    pub visibility: &'static str,
+    /// Отрезок, когда МКС видна глазом (освещена на тёмном небе), если такой есть.
+    pub visible_from: Option<DateTime<Utc>>,
+    pub visible_to: Option<DateTime<Utc>>,
+}
+
+struct Sample {
+    az: f64,
+    el: f64,
+    sunlit: bool,
+    sky_dark: bool,
+}
+
+fn sample(model: &Sgp4, obs: &Observer, t: DateTime<Utc>) -> Result<Sample, Sgp4Error> {
+    let sv = model.at(t)?;
+    let (az, el) = obs.look_angles(sgp4::teme_to_ecef(sv.r, t));
+    let (sun_dir, sun_dist) = sun_eci(t);
+    let sun_ecef = sgp4::teme_to_ecef(sun_dir.map(|x| x * sun_dist), t);
+    let (_, sun_el) = obs.look_angles(sun_ecef);
+    Ok(Sample { az, el, sunlit: is_sunlit(sv.r, sun_dir), sky_dark: sun_el < SUN_DARK_ELEVATION_DEG })
+}
+
+fn elevation(model: &Sgp4, obs: &Observer, t: DateTime<Utc>) -> Result<f64, Sgp4Error> {
+    let sv = model.at(t)?;
+    Ok(obs.look_angles(sgp4::teme_to_ecef(sv.r, t)).1)
+}
+
+/// Момент пересечения горизонта между `a` (ниже/выше) и `b` (наоборот) с точностью до секунды.
+fn bisect_horizon(model: &Sgp4, obs: &Observer, mut a: DateTime<Utc>, mut b: DateTime<Utc>) -> Result<DateTime<Utc>, Sgp4Error> {
+    let rising = elevation(model, obs, a)? < 0.0;
+    while (b - a).num_milliseconds() > 1000 {
+        let mid = a + (b - a) / 2;
+        if (elevation(model, obs, mid)? < 0.0) == rising { a = mid } else { b = mid }
+    }
+    Ok(whole_sec(b))
+}
+
+/// Максимум угла места на [a, b] тернарным поиском (внутри пролёта функция унимодальна).
+fn culmination(model: &Sgp4, obs: &Observer, mut a: DateTime<Utc>, mut b: DateTime<Utc>) -> Result<DateTime<Utc>, Sgp4Error> {
+    while (b - a).num_milliseconds() > 1000 {
+        let m1 = a + (b - a) / 3;
+        let m2 = b - (b - a) / 3;
+        if elevation(model, obs, m1)? < elevation(model, obs, m2)? { a = m1 } else { b = m2 }
+    }
+    Ok(whole_sec(a + (b - a) / 2))
+}
+
+fn whole_sec(t: DateTime<Utc>) -> DateTime<Utc> {
+    t.with_nanosecond(0).unwrap_or(t)
+}
+
+fn point(model: &Sgp4, obs: &Observer, t: DateTime<Utc>) -> Result<PassPoint, Sgp4Error> {
+    let s = sample(model, obs, t)?;
+    Ok(PassPoint { t, azimuth_deg: s.az, elevation_deg: s.el.max(0.0) })
+}
+
+/// Все пролёты над горизонтом, начавшиеся в [from, to), с максимальным углом места не ниже
+/// `min_elevation_deg`. Пролёт, идущий в момент `to`, досчитывается до захода.
+pub fn find_passes(
+    model: &Sgp4,
+    obs: &Observer,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    min_elevation_deg: f64,
+) -> Result<Vec<Pass>, Sgp4Error> {
+    let step = Duration::seconds(SCAN_STEP_SEC);
+    let mut passes = Vec::new();
+    let from = whole_sec(from);
+    let mut t = from;
+    let mut prev_el = elevation(model, obs, t)?;
+    // если наблюдение начинается посреди пролёта — считаем восходом начало окна
+    let mut rise: Option<DateTime<Utc>> = (prev_el >= 0.0).then_some(from);
+
+    while t < to {
+        let next = (t + step).min(to);
+        let el = elevation(model, obs, next)?;
+        if prev_el < 0.0 && el >= 0.0 {
+            rise = Some(bisect_horizon(model, obs, t, next)?);
+        } else if prev_el >= 0.0 && el < 0.0 {
+            if let Some(r) = rise.take() {
+                let set = bisect_horizon(model, obs, t, next)?;
+                if let Some(p) = build_pass(model, obs, r, set, min_elevation_deg)? {
+                    passes.push(p);
+                }
+            }
+        }
+        prev_el = el;
+        t = next;
+    }
+
+    // спутник ещё над горизонтом: ищем заход за окном, в крайнем случае обрезаем
+    if let Some(r) = rise {
+        let limit = to + Duration::minutes(MAX_OVERRUN_MIN);
+        let set = loop {
+            if t >= limit {
+                break t;
+            }
+            let next = (t + step).min(limit);
+            if elevation(model, obs, next)? < 0.0 {
+                break bisect_horizon(model, obs, t, next)?;
+            }
+            t = next;
+        };
+        if let Some(p) = build_pass(model, obs, r, set, min_elevation_deg)? {
+            passes.push(p);
+        }
+    }
+    Ok(passes)
+}
+
+fn build_pass(
+    model: &Sgp4,
+    obs: &Observer,
+    rise: DateTime<Utc>,
+    set: DateTime<Utc>,
+    min_elevation_deg: f64,
+) -> Result<Option<Pass>, Sgp4Error> {
+    let culm_t = culmination(model, obs, rise, set)?;
+    let culmination = point(model, obs, culm_t)?;
+    if culmination.elevation_deg < min_elevation_deg {
+        return Ok(None);
+    }
+
+    // освещённость с шагом 10 с (пролёт длится не дольше ~12 минут)
+    let (mut any_dark, mut visible_from, mut visible_to) = (false, None, None);
+    let mut t = rise;
+    while t <= set {
+        let s = sample(model, obs, t)?;
+        any_dark |= s.sky_dark;
+        if s.sky_dark && s.sunlit && s.el >= 0.0 {
+            visible_from.get_or_insert(t);
+            visible_to = Some(t);
+        }
+        t += Duration::seconds(10);
+    }
+    let visibility = match (visible_from.is_some(), any_dark) {
+        (true, _) => "visible",
+        (false, true) => "eclipsed",
+        (false, false) => "daylight",
+    };
+
+    Ok(Some(Pass {
+        rise: point(model, obs, rise)?,
+        culmination,
+        set: point(model, obs, set)?,
+        duration_sec: (set - rise).num_seconds(),
+        visibility,
+        visible_from,
+        visible_to,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sgp4::Tle;
+
+    fn iss() -> Sgp4 {
+        let tle = Tle::parse(
+            Some("ISS (ZARYA)"),
+            "1 25544U 98067A   26289.50000000  .00016717  00000-0  10270-3 0  9009",
+            "2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.50125391563533",
+        ).unwrap();
+        Sgp4::new(&tle).unwrap()
+    }
+
+    const MOSCOW: Observer = Observer { lat_deg: 55.75, lon_deg: 37.62, alt_km: 0.15 };
+
+    #[test]
+    fn pass_cut_by_window_end_is_completed() {
+        let model = iss();
+        let from = model.epoch;
+        let all = find_passes(&model, &MOSCOW, from, from + Duration::days(1), 0.0).unwrap();
+        let full = all.first().expect("ISS passes over Moscow within a day");
+
+        // окно заканчивается посреди пролёта
+        let to = full.rise.t + Duration::seconds(full.duration_sec / 2);
+        let cut = find_passes(&model, &MOSCOW, from, to, 0.0).unwrap();
+        let last = cut.last().expect("the pass in progress at `to` is reported");
+        assert_eq!(last.rise.t, full.rise.t);
+        assert!((last.set.t - full.set.t).num_seconds().abs() <= 1);
+    }
+
+    #[test]
+    fn passes_rise_and_set_at_horizon() {
+        let model = iss();
+        let from = model.epoch;
+        for p in find_passes(&model, &MOSCOW, from, from + Duration::days(2), 0.0).unwrap() {
+            assert!(p.rise.t < p.culmination.t && p.culmination.t < p.set.t);
+            assert!(p.rise.elevation_deg < 0.5 && p.set.elevation_deg < 0.5);
+            assert!(p.duration_sec > 0 && p.duration_sec < 15 * 60);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Квоты ключей api.nasa.gov: остаток из X-RateLimit-Remaining по каждому ключу,
+//! смена ключа на 429 и откладывание низкоприоритетных опросов, когда остаток мал.
+
+use std::sync::{Mutex, RwLock};
+
+use chrono::{DateTime, Utc};
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Окно лимита api.nasa.gov: скользящий час. Остаток из X-RateLimit-Remaining старше этого
+/// уже ничего не говорит — за час все учтённые запросы из окна выпали.
+const RATE_LIMIT_WINDOW_SECONDS: i64 = 3600;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    #[default]
+    Normal,
+    /// Откладывается, когда у лучшего ключа осталось меньше `reserve` запросов.
+    Low,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct NasaPolicy {
+    /// Запас запросов, который не тратим на низкоприоритетные опросы.
+    pub reserve: u32,
+    /// Сколько не трогаем ключ после 429.
+    pub cooldown_seconds: u64,
+    /// Ключи из NASA_API_KEY и NASA_API_KEYS; в файле не задаются.
+    #[serde(skip)]
+    pub keys: Vec<String>,
+}
+
+impl Default for NasaPolicy {
+    fn default() -> Self {
+        Self { reserve: 100, cooldown_seconds: 3600, keys: Vec::new() }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QuotaError {
+    #[error("nasa quota low ({remaining} requests left), low-priority fetch deferred")]
+    Deferred { remaining: u32 },
+    #[error("all nasa api keys are rate limited until {until}")]
+    Exhausted { until: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone)]
+struct KeyState {
+    key: String,
+    limit: Option<u32>,
+    remaining: Option<u32>,
+    observed_at: Option<DateTime<Utc>>,
+    limited_until: Option<DateTime<Utc>>,
+    requests: u64,
+    rate_limited: u64,
+}
+
+impl KeyState {
+    fn new(key: String) -> Self {
+        Self { key, limit: None, remaining: None, observed_at: None, limited_until: None, requests: 0, rate_limited: 0 }
+    }
+
+    /// Остаток, если он ещё что-то значит: через час окно лимита целиком сдвинулось.
+    fn remaining(&self, now: DateTime<Utc>) -> Option<u32> {
+        let fresh = self.observed_at? + chrono::Duration::seconds(RATE_LIMIT_WINDOW_SECONDS) > now;
+        fresh.then_some(self.remaining?)
+    }
+
+    fn available(&self, now: DateTime<Utc>) -> bool {
+        self.limited_until.is_none_or(|t| t <= now)
+    }
+}
+
+#[derive(Default)]
+pub struct NasaKeys {
+    policy: RwLock<NasaPolicy>,
+    keys: Mutex<Vec<KeyState>>,
+}
+
+impl NasaKeys {
+    /// Счётчики ключей, оставшихся в списке, сохраняются.
+    pub fn configure(&self, policy: NasaPolicy) {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        let mut old = std::mem::take(&mut *keys);
+        *keys = policy.keys.iter().map(|k| match old.iter().position(|s| &s.key == k) {
+            Some(i) => old.swap_remove(i),
+            None => KeyState::new(k.clone()),
+        }).collect();
+        *self.policy.write().unwrap_or_else(|e| e.into_inner()) = policy;
+    }
+
+    fn policy(&self) -> NasaPolicy {
+        self.policy.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn with_keys<T>(&self, f: impl FnOnce(&mut Vec<KeyState>) -> T) -> T {
+        f(&mut self.keys.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Ключ с наибольшим остатком (неизвестный остаток — как полный).
+    /// `None` — ключей нет, запрос уходит без api_key.
+    pub fn acquire(&self, priority: Priority) -> Result<Option<String>, QuotaError> {
+        self.acquire_at(priority, Utc::now())
+    }
+
+    fn acquire_at(&self, priority: Priority, now: DateTime<Utc>) -> Result<Option<String>, QuotaError> {
+        let policy = self.policy();
+        self.with_keys(|keys| {
+            if keys.is_empty() {
+                return Ok(None);
+            }
+            let Some(best) = keys.iter_mut()
+                .filter(|k| k.available(now))
+                .max_by_key(|k| k.remaining(now).unwrap_or(u32::MAX))
+            else {
+                let until = keys.iter().filter_map(|k| k.limited_until).min().unwrap_or(now);
+                return Err(QuotaError::Exhausted { until });
+            };
+            if let Some(remaining) = best.remaining(now) {
+                if priority == Priority::Low && remaining < policy.reserve {
+                    return Err(QuotaError::Deferred { remaining });
+                }
+            }
+            best.requests += 1;
+            Ok(Some(best.key.clone()))
+        })
+    }
+
+    /// Учитывает ответ api.nasa.gov. true — ключ упёрся в лимит и есть другой, можно повторить.
+    pub fn observe(&self, key: &str, status: StatusCode, headers: &HeaderMap) -> bool {
+        self.observe_at(key, status, headers, Utc::now())
+    }
+
+    fn observe_at(&self, key: &str, status: StatusCode, headers: &HeaderMap, now: DateTime<Utc>) -> bool {
+        let policy = self.policy();
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<u32>().ok();
+        self.with_keys(|keys| {
+            let Some(k) = keys.iter_mut().find(|k| k.key == key) else { return false };
+            if let Some(remaining) = header("x-ratelimit-remaining") {
+                k.remaining = Some(remaining);
+                k.limit = header("x-ratelimit-limit").or(k.limit);
+                k.observed_at = Some(now);
+            }
+            if status != StatusCode::TOO_MANY_REQUESTS {
+                return false;
+            }
+            k.rate_limited += 1;
+            k.remaining = Some(0);
+            k.observed_at = Some(now);
+            k.limited_until = Some(now + chrono::Duration::seconds(policy.cooldown_seconds as i64));
+            tracing::warn!("nasa key {} rate limited until {:?}", mask(key), k.limited_until);
+            keys.iter().any(|k| k.available(now))
+        })
+    }
+
+    /// Состояние для /admin/quota; ключи показываем только последними символами.
+    pub fn overview(&self) -> Value {
+        let policy = self.policy();
+        let now = Utc::now();
+        let keys: Vec<Value> = self.with_keys(|keys| keys.iter().map(|k| serde_json::json!({
+            "key": mask(&k.key),
+            "available": k.available(now),
+            "limit": k.limit,
+            "remaining": k.remaining(now),
+            "observed_at": k.observed_at,
+            "limited_until": k.limited_until.filter(|t| *t > now),
+            "requests": k.requests,
+            "rate_limited": k.rate_limited,
+        })).collect());
+        serde_json::json!({
+            "reserve": policy.reserve,
+            "cooldown_seconds": policy.cooldown_seconds,
+            "keys": keys,
+        })
+    }
+}
+
+fn mask(key: &str) -> String {
+    if key == "DEMO_KEY" {
+        return key.to_string();
+    }
+    let tail: String = key.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+    format!("…{tail}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(sec: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + sec, 0).unwrap()
+    }
+
+    fn pool(keys: &[&str]) -> NasaKeys {
+        let nasa = NasaKeys::default();
+        nasa.configure(NasaPolicy { reserve: 100, cooldown_seconds: 600, keys: keys.iter().map(|k| k.to_string()).collect() });
+        nasa
+    }
+
+    fn remaining(n: u32) -> HeaderMap {
+        HeaderMap::from_iter([
+            ("x-ratelimit-remaining".parse().unwrap(), n.to_string().parse().unwrap()),
+            ("x-ratelimit-limit".parse().unwrap(), "1000".parse().unwrap()),
+        ])
+    }
+
+    fn key(r: Result<Option<String>, QuotaError>) -> String {
+        r.unwrap().unwrap()
+    }
+
+    #[test]
+    fn no_keys_means_no_api_key() {
+        assert_eq!(pool(&[]).acquire_at(Priority::Low, at(0)).unwrap(), None);
+    }
+
+    #[test]
+    fn picks_the_key_with_most_remaining() {
+        let nasa = pool(&["a", "b", "c"]);
+        assert!(!nasa.observe_at("a", StatusCode::OK, &remaining(300), at(0)));
+        nasa.observe_at("b", StatusCode::OK, &remaining(900), at(0));
+        nasa.observe_at("c", StatusCode::OK, &remaining(500), at(0));
+        assert_eq!(key(nasa.acquire_at(Priority::Normal, at(1))), "b");
+        // неизвестный остаток считается полным
+        nasa.configure(NasaPolicy { keys: vec!["a".into(), "b".into(), "d".into()], ..nasa.policy() });
+        assert_eq!(key(nasa.acquire_at(Priority::Normal, at(1))), "d");
+    }
+
+    #[test]
+    fn low_priority_is_deferred_below_reserve() {
+        let nasa = pool(&["a"]);
+        nasa.observe_at("a", StatusCode::OK, &remaining(99), at(0));
+        assert!(matches!(nasa.acquire_at(Priority::Low, at(1)), Err(QuotaError::Deferred { remaining: 99 })));
+        assert_eq!(key(nasa.acquire_at(Priority::Normal, at(1))), "a");
+        nasa.observe_at("a", StatusCode::OK, &remaining(100), at(2));
+        assert_eq!(key(nasa.acquire_at(Priority::Low, at(3))), "a");
+    }
+
+    #[test]
+    fn remaining_expires_after_the_rate_limit_window() {
+        let nasa = pool(&["a"]);
+        nasa.observe_at("a", StatusCode::OK, &remaining(5), at(0));
+        assert!(nasa.acquire_at(Priority::Low, at(3599)).is_err());
+        // окно сдвинулось — старый остаток не в счёт, хотя cooldown_seconds всего 600
+        assert_eq!(key(nasa.acquire_at(Priority::Low, at(3600))), "a");
+    }
+
+    #[test]
+    fn rate_limited_keys_rotate_then_exhaust() {
+        let nasa = pool(&["a", "b"]);
+        // 429 по "a": есть "b", можно повторить
+        assert!(nasa.observe_at("a", StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), at(0)));
+        assert_eq!(key(nasa.acquire_at(Priority::Normal, at(1))), "b");
+        assert!(!nasa.observe_at("b", StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), at(10)));
+        assert!(matches!(
+            nasa.acquire_at(Priority::Normal, at(11)),
+            Err(QuotaError::Exhausted { until }) if until == at(600)
+        ));
+        // после паузы ключ снова в ходу: обычный приоритет на запас не смотрит
+        assert_eq!(key(nasa.acquire_at(Priority::Normal, at(600))), "a");
+    }
+
+    #[test]
+    fn unknown_key_and_bad_headers_are_ignored() {
+        let nasa = pool(&["a"]);
+        assert!(!nasa.observe_at("zzz", StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), at(0)));
+        let garbage = HeaderMap::from_iter([("x-ratelimit-remaining".parse().unwrap(), "lots".parse().unwrap())]);
+        nasa.observe_at("a", StatusCode::OK, &garbage, at(0));
+        assert_eq!(key(nasa.acquire_at(Priority::Low, at(1))), "a");
+        assert_eq!(mask("abcdef123456"), "…3456");
+        assert_eq!(mask("DEMO_KEY"), "DEMO_KEY");
+    }
+}
@@ -0,0 +1,586 @@
+//! Разбор TLE и распространение орбиты по модели SGP4 (только околоземный вариант:
+//! для МКС с периодом ~92 мин глубококосмические поправки SDP4 не нужны).
+//! Формулы и константы WGS-72 — по Vallado et al., "Revisiting Spacetrack Report #3" (2006).
+
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+const TWO_PI: f64 = 2.0 * PI;
+const X2O3: f64 = 2.0 / 3.0;
+
+// WGS-72
+const MU: f64 = 398600.8;
+const RE_KM: f64 = 6378.135;
+const J2: f64 = 0.001082616;
+const J3: f64 = -0.00000253881;
+const J4: f64 = -0.00000165597;
+
+// WGS-84 для перевода в геодезические координаты
+const WGS84_A_KM: f64 = 6378.137;
+const WGS84_F: f64 = 1.0 / 298.257223563;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Sgp4Error {
+    #[error("malformed TLE: {0}")]
+    Tle(String),
+    #[error("deep-space orbits (period >= 225 min) are not supported")]
+    DeepSpace,
+    #[error("propagation diverged: {0}")]
+    Diverged(&'static str),
+}
+
+fn xke() -> f64 {
+    60.0 / (RE_KM * RE_KM * RE_KM / MU).sqrt()
+}
+
+/// Двухстрочный набор элементов.
+#[derive(Debug, Clone)]
+pub struct Tle {
+    pub name: Option<String>,
+    pub line1: String,
+    pub line2: String,
+    pub norad_id: u32,
+    pub epoch: DateTime<Utc>,
+    bstar: f64,
+    inclination: f64,
+    raan: f64,
+    eccentricity: f64,
+    arg_perigee: f64,
+    mean_anomaly: f64,
+    /// рад/мин (Kozai)
+    mean_motion: f64,
+}
+
+fn field(line: &str, from: usize, to: usize) -> Result<&str, Sgp4Error> {
+    line.get(from..to)
+        .map(str::trim)
+        .ok_or_else(|| Sgp4Error::Tle(format!("line too short for columns {}..{}", from + 1, to)))
+}
+
+fn float(line: &str, from: usize, to: usize) -> Result<f64, Sgp4Error> {
+    let s = field(line, from, to)?;
+    s.parse().map_err(|_| Sgp4Error::Tle(format!("bad number '{s}'")))
+}
+
+/// Числа вида " 12345-4" с подразумеваемой десятичной точкой: 0.12345e-4.
+fn implied_decimal(s: &str) -> Result<f64, Sgp4Error> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(0.0);
+    }
+    let (mant, exp) = match s.rfind(['-', '+']) {
+        Some(i) if i > 0 => (&s[..i], &s[i..]),
+        _ => (s, "0"),
+    };
+    let (sign, digits) = match mant.strip_prefix('-') {
+        Some(d) => (-1.0, d),
+        None => (1.0, mant.trim_start_matches('+')),
+    };
+    let m: f64 = format!("0.{digits}").parse().map_err(|_| Sgp4Error::Tle(format!("bad number '{s}'")))?;
+    let e: i32 = exp.parse().map_err(|_| Sgp4Error::Tle(format!("bad exponent '{s}'")))?;
+    Ok(sign * m * 10f64.powi(e))
+}
+
+fn checksum_ok(line: &str) -> bool {
+    let Some(expected) = line.chars().nth(68).and_then(|c| c.to_digit(10)) else { return true };
+    let sum: u32 = line.chars().take(68).map(|c| match c {
+        '-' => 1,
+        c => c.to_digit(10).unwrap_or(0),
+    }).sum();
+    sum % 10 == expected
+}
+
+impl Tle {
+    pub fn parse(name: Option<&str>, line1: &str, line2: &str) -> Result<Self, Sgp4Error> {
+        let (line1, line2) = (line1.trim_end(), line2.trim_end());
+        if !line1.starts_with("1 ") || !line2.starts_with("2 ") {
+            return Err(Sgp4Error::Tle("lines must start with '1 ' and '2 '".into()));
+        }
+        if !checksum_ok(line1) || !checksum_ok(line2) {
+            return Err(Sgp4Error::Tle("checksum mismatch".into()));
+        }
+        let norad_id: u32 = field(line1, 2, 7)?.parse()
+            .map_err(|_| Sgp4Error::Tle("bad catalog number".into()))?;
+
+        let yy: i32 = field(line1, 18, 20)?.parse().map_err(|_| Sgp4Error::Tle("bad epoch year".into()))?;
+        let year = if yy < 57 { 2000 + yy } else { 1900 + yy };
+        let day = float(line1, 20, 32)?;
+        let jan1 = NaiveDate::from_ymd_opt(year, 1, 1)
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .ok_or_else(|| Sgp4Error::Tle("bad epoch".into()))?
+            .and_utc();
+        let epoch = jan1 + Duration::microseconds(((day - 1.0) * 86_400e6).round() as i64);
+
+        let bstar = implied_decimal(field(line1, 53, 61)?)?;
+        let inclination = float(line2, 8, 16)?.to_radians();
+        let raan = float(line2, 17, 25)?.to_radians();
+        let eccentricity = implied_decimal(field(line2, 26, 33)?)?;
+        let arg_perigee = float(line2, 34, 42)?.to_radians();
+        let mean_anomaly = float(line2, 43, 51)?.to_radians();
+        let mean_motion = float(line2, 52, 63)? * TWO_PI / 1440.0;
+
+        Ok(Self {
+            name: name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+            line1: line1.to_string(),
+            line2: line2.to_string(),
+            norad_id,
+            epoch,
+            bstar,
+            inclination,
+            raan,
+            eccentricity,
+            arg_perigee,
+            mean_anomaly,
+            mean_motion,
+        })
+    }
+
+    /// Первый набор из текста в формате 2LE/3LE (как отдаёт CelesTrak), опционально по номеру NORAD.
+    /// Испорченные наборы пропускаются; если подходящего нет, ошибка первого из них.
+    pub fn find_in(text: &str, norad_id: Option<u32>) -> Result<Self, Sgp4Error> {
+        let lines: Vec<&str> = text.lines().map(str::trim_end).filter(|l| !l.trim().is_empty()).collect();
+        let mut skipped = None;
+        for i in 0..lines.len() {
+            if !lines[i].starts_with("1 ") || !lines.get(i + 1).is_some_and(|l| l.starts_with("2 ")) {
+                continue;
+            }
+            let name = i.checked_sub(1).map(|j| lines[j])
+                .filter(|l| !l.starts_with("1 ") && !l.starts_with("2 "))
+                .map(|l| l.trim_start_matches("0 "));
+            let tle = match Tle::parse(name, lines[i], lines[i + 1]) {
+                Ok(tle) => tle,
+                Err(e) => {
+                    tracing::warn!("skipping bad element set {:?}: {e}", name.unwrap_or(lines[i]));
+                    skipped.get_or_insert(e);
+                    continue;
+                }
+            };
+            if norad_id.is_none_or(|id| id == tle.norad_id) {
+                return Ok(tle);
+            }
+        }
+        Err(skipped.unwrap_or_else(|| Sgp4Error::Tle("no element set found".into())))
+    }
+}
+
+/// Состояние в системе TEME: км и км/с.
+#[derive(Debug, Clone, Copy)]
+pub struct StateVector {
+    pub r: [f64; 3],
+    pub v: [f64; 3],
+}
+
+/// Инициализированная модель SGP4 для одного TLE.
+#[derive(Debug, Clone)]
+pub struct Sgp4 {
+    pub epoch: DateTime<Utc>,
+    ecco: f64,
+    inclo: f64,
+    nodeo: f64,
+    argpo: f64,
+    mo: f64,
+    bstar: f64,
+    no: f64,
+    isimp: bool,
+    aycof: f64,
+    con41: f64,
+    cc1: f64,
+    cc4: f64,
+    cc5: f64,
+    d2: f64,
+    d3: f64,
+    d4: f64,
+    delmo: f64,
+    eta: f64,
+    argpdot: f64,
+    omgcof: f64,
+    sinmao: f64,
+    t2cof: f64,
+    t3cof: f64,
+    t4cof: f64,
+    t5cof: f64,
+    x1mth2: f64,
+    x7thm1: f64,
+    mdot: f64,
+    nodedot: f64,
+    xlcof: f64,
+    xmcof: f64,
+    nodecf: f64,
+}
+
+impl Sgp4 {
+    pub fn new(tle: &Tle) -> Result<Self, Sgp4Error> {
+        let xke = xke();
+        let j3oj2 = J3 / J2;
+        let (ecco, inclo, bstar) = (tle.eccentricity, tle.inclination, tle.bstar);
+
+        // initl: восстановление «un-Kozai» среднего движения
+        let ak = (xke / tle.mean_motion).powf(X2O3);
+        let cosio = inclo.cos();
+        let cosio2 = cosio * cosio;
+        let eccsq = ecco * ecco;
+        let omeosq = 1.0 - eccsq;
+        let rteosq = omeosq.sqrt();
+        let d1 = 0.75 * J2 * (3.0 * cosio2 - 1.0) / (rteosq * omeosq);
+        let mut del = d1 / (ak * ak);
+        let adel = ak * (1.0 - del * del - del * (1.0 / 3.0 + 134.0 * del * del / 81.0));
+        del = d1 / (adel * adel);
+        let no = tle.mean_motion / (1.0 + del);
+        if TWO_PI / no >= 225.0 {
+            return Err(Sgp4Error::DeepSpace);
+        }
+        let ao = (xke / no).powf(X2O3);
+        let sinio = inclo.sin();
+        let po = ao * omeosq;
+        let con42 = 1.0 - 5.0 * cosio2;
+        let con41 = -con42 - cosio2 - cosio2;
+        let posq = po * po;
+        let rp = ao * (1.0 - ecco);
+
+        // sgp4init
+        let ss = 78.0 / RE_KM + 1.0;
+        let qzms2t = ((120.0 - 78.0) / RE_KM).powi(4);
+        let isimp = rp < 220.0 / RE_KM + 1.0;
+        let mut sfour = ss;
+        let mut qzms24 = qzms2t;
+        let perige = (rp - 1.0) * RE_KM;
+        if perige < 156.0 {
+            sfour = if perige < 98.0 { 20.0 } else { perige - 78.0 };
+            qzms24 = ((120.0 - sfour) / RE_KM).powi(4);
+            sfour = sfour / RE_KM + 1.0;
+        }
+        let pinvsq = 1.0 / posq;
+        let tsi = 1.0 / (ao - sfour);
+        let eta = ao * ecco * tsi;
+        let etasq = eta * eta;
+        let eeta = ecco * eta;
+        let psisq = (1.0 - etasq).abs();
+        let coef = qzms24 * tsi.powi(4);
+        let coef1 = coef / psisq.powf(3.5);
+        let cc2 = coef1 * no * (ao * (1.0 + 1.5 * etasq + eeta * (4.0 + etasq))
+            + 0.375 * J2 * tsi / psisq * con41 * (8.0 + 3.0 * etasq * (8.0 + etasq)));
+        let cc1 = bstar * cc2;
+        let cc3 = if ecco > 1.0e-4 { -2.0 * coef * tsi * j3oj2 * no * sinio / ecco } else { 0.0 };
+        let x1mth2 = 1.0 - cosio2;
+        let cc4 = 2.0 * no * coef1 * ao * omeosq
+            * (eta * (2.0 + 0.5 * etasq) + ecco * (0.5 + 2.0 * etasq)
+                - J2 * tsi / (ao * psisq)
+                    * (-3.0 * con41 * (1.0 - 2.0 * eeta + etasq * (1.5 - 0.5 * eeta))
+                        + 0.75 * x1mth2 * (2.0 * etasq - eeta * (1.0 + etasq)) * (2.0 * tle.arg_perigee).cos()));
+        let cc5 = 2.0 * coef1 * ao * omeosq * (1.0 + 2.75 * (etasq + eeta) + eeta * etasq);
+        let cosio4 = cosio2 * cosio2;
+        let temp1 = 1.5 * J2 * pinvsq * no;
+        let temp2 = 0.5 * temp1 * J2 * pinvsq;
+        let temp3 = -0.46875 * J4 * pinvsq * pinvsq * no;
+        let mdot = no + 0.5 * temp1 * rteosq * con41 + 0.0625 * temp2 * rteosq * (13.0 - 78.0 * cosio2 + 137.0 * cosio4);
+        let argpdot = -0.5 * temp1 * con42
+            + 0.0625 * temp2 * (7.0 - 114.0 * cosio2 + 395.0 * cosio4)
+            + temp3 * (3.0 - 36.0 * cosio2 + 49.0 * cosio4);
+        let xhdot1 = -temp1 * cosio;
+        let nodedot = xhdot1 + (0.5 * temp2 * (4.0 - 19.0 * cosio2) + 2.0 * temp3 * (3.0 - 7.0 * cosio2)) * cosio;
+        let omgcof = bstar * cc3 * tle.arg_perigee.cos();
+        let xmcof = if ecco > 1.0e-4 { -X2O3 * coef * bstar / eeta } else { 0.0 };
+        let nodecf = 3.5 * omeosq * xhdot1 * cc1;
+        let t2cof = 1.5 * cc1;
+        let xlcof_den = if (cosio + 1.0).abs() > 1.5e-12 { 1.0 + cosio } else { 1.5e-12 };
+        let xlcof = -0.25 * j3oj2 * sinio * (3.0 + 5.0 * cosio) / xlcof_den;
+        let aycof = -0.5 * j3oj2 * sinio;
+        let delmo = (1.0 + eta * tle.mean_anomaly.cos()).powi(3);
+        let sinmao = tle.mean_anomaly.sin();
+        let x7thm1 = 7.0 * cosio2 - 1.0;
+
+        let (mut d2, mut d3, mut d4, mut t3cof, mut t4cof, mut t5cof) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        if !isimp {
+            let cc1sq = cc1 * cc1;
+            d2 = 4.0 * ao * tsi * cc1sq;
+            let temp = d2 * tsi * cc1 / 3.0;
+            d3 = (17.0 * ao + sfour) * temp;
+            d4 = 0.5 * temp * ao * tsi * (221.0 * ao + 31.0 * sfour) * cc1;
+            t3cof = d2 + 2.0 * cc1sq;
+            t4cof = 0.25 * (3.0 * d3 + cc1 * (12.0 * d2 + 10.0 * cc1sq));
+            t5cof = 0.2 * (3.0 * d4 + 12.0 * cc1 * d3 + 6.0 * d2 * d2 + 15.0 * cc1sq * (2.0 * d2 + cc1sq));
+        }
+
+        Ok(Self {
+            epoch: tle.epoch,
+            ecco, inclo, nodeo: tle.raan, argpo: tle.arg_perigee, mo: tle.mean_anomaly, bstar, no,
+            isimp, aycof, con41, cc1, cc4, cc5, d2, d3, d4, delmo, eta, argpdot, omgcof, sinmao,
+            t2cof, t3cof, t4cof, t5cof, x1mth2, x7thm1, mdot, nodedot, xlcof, xmcof, nodecf,
+        })
+    }
+
+    /// Состояние на момент `at`.
+    pub fn at(&self, at: DateTime<Utc>) -> Result<StateVector, Sgp4Error> {
+        let minutes = (at - self.epoch).num_milliseconds() as f64 / 60_000.0;
+        self.propagate(minutes)
+    }
+
+    /// Состояние через `t` минут после эпохи TLE.
+    pub fn propagate(&self, t: f64) -> Result<StateVector, Sgp4Error> {
+        let xke = xke();
+
+        let xmdf = self.mo + self.mdot * t;
+        let argpdf = self.argpo + self.argpdot * t;
+        let nodedf = self.nodeo + self.nodedot * t;
+        let mut argpm = argpdf;
+        let mut mm = xmdf;
+        let t2 = t * t;
+        let mut nodem = nodedf + self.nodecf * t2;
+        let mut tempa = 1.0 - self.cc1 * t;
+        let mut tempe = self.bstar * self.cc4 * t;
+        let mut templ = self.t2cof * t2;
+
+        if !self.isimp {
+            let delomg = self.omgcof * t;
+            let delm = self.xmcof * ((1.0 + self.eta * xmdf.cos()).powi(3) - self.delmo);
+            let temp = delomg + delm;
+            mm = xmdf + temp;
+            argpm = argpdf - temp;
+            let t3 = t2 * t;
+            let t4 = t3 * t;
+            tempa = tempa - self.d2 * t2 - self.d3 * t3 - self.d4 * t4;
+            tempe += self.bstar * self.cc5 * (mm.sin() - self.sinmao);
+            templ += self.t3cof * t3 + t4 * (self.t4cof + t * self.t5cof);
+        }
+
+        let am = (xke / self.no).powf(X2O3) * tempa * tempa;
+        let nm = xke / am.powf(1.5);
+        let mut em = self.ecco - tempe;
+        if !(-0.001..1.0).contains(&em) {
+            return Err(Sgp4Error::Diverged("eccentricity out of range"));
+        }
+        em = em.max(1.0e-6);
+        mm += self.no * templ;
+        let xlm = mm + argpm + nodem;
+        nodem %= TWO_PI;
+        argpm %= TWO_PI;
+        let xlm = xlm % TWO_PI;
+        mm = (xlm - argpm - nodem) % TWO_PI;
+
+        let (sinip, cosip) = self.inclo.sin_cos();
+
+        // долгопериодические члены
+        let axnl = em * argpm.cos();
+        let temp = 1.0 / (am * (1.0 - em * em));
+        let aynl = em * argpm.sin() + temp * self.aycof;
+        let xl = mm + argpm + nodem + temp * self.xlcof * axnl;
+
+        // уравнение Кеплера
+        let u = (xl - nodem) % TWO_PI;
+        let mut eo1 = u;
+        let (mut sineo1, mut coseo1) = (0.0, 0.0);
+        let mut tem5: f64 = 9999.9;
+        let mut ktr = 1;
+        while tem5.abs() >= 1.0e-12 && ktr <= 10 {
+            (sineo1, coseo1) = eo1.sin_cos();
+            tem5 = 1.0 - coseo1 * axnl - sineo1 * aynl;
+            tem5 = (u - aynl * coseo1 + axnl * sineo1 - eo1) / tem5;
+            if tem5.abs() >= 0.95 {
+                tem5 = 0.95f64.copysign(tem5);
+            }
+            eo1 += tem5;
+            ktr += 1;
+        }
+
+        // короткопериодические члены
+        let ecose = axnl * coseo1 + aynl * sineo1;
+        let esine = axnl * sineo1 - aynl * coseo1;
+        let el2 = axnl * axnl + aynl * aynl;
+        let pl = am * (1.0 - el2);
+        if pl < 0.0 {
+            return Err(Sgp4Error::Diverged("semi-latus rectum < 0"));
+        }
+        let rl = am * (1.0 - ecose);
+        let rdotl = am.sqrt() * esine / rl;
+        let rvdotl = pl.sqrt() / rl;
+        let betal = (1.0 - el2).sqrt();
+        let temp = esine / (1.0 + betal);
+        let sinu = am / rl * (sineo1 - aynl - axnl * temp);
+        let cosu = am / rl * (coseo1 - axnl + aynl * temp);
+        let mut su = sinu.atan2(cosu);
+        let sin2u = (cosu + cosu) * sinu;
+        let cos2u = 1.0 - 2.0 * sinu * sinu;
+        let temp = 1.0 / pl;
+        let temp1 = 0.5 * J2 * temp;
+        let temp2 = temp1 * temp;
+
+        let mrt = rl * (1.0 - 1.5 * temp2 * betal * self.con41) + 0.5 * temp1 * self.x1mth2 * cos2u;
+        su -= 0.25 * temp2 * self.x7thm1 * sin2u;
+        let xnode = nodem + 1.5 * temp2 * cosip * sin2u;
+        let xinc = self.inclo + 1.5 * temp2 * cosip * sinip * cos2u;
+        let mvt = rdotl - nm * temp1 * self.x1mth2 * sin2u / xke;
+        let rvdot = rvdotl + nm * temp1 * (self.x1mth2 * cos2u + 1.5 * self.con41) / xke;
+
+        if mrt < 1.0 {
+            return Err(Sgp4Error::Diverged("orbit decayed"));
+        }
+
+        let (sinsu, cossu) = su.sin_cos();
+        let (snod, cnod) = xnode.sin_cos();
+        let (sini, cosi) = xinc.sin_cos();
+        let xmx = -snod * cosi;
+        let xmy = cnod * cosi;
+        let ux = xmx * sinsu + cnod * cossu;
+        let uy = xmy * sinsu + snod * cossu;
+        let uz = sini * sinsu;
+        let vx = xmx * cossu - cnod * sinsu;
+        let vy = xmy * cossu - snod * sinsu;
+        let vz = sini * cossu;
+
+        let vkmpersec = RE_KM * xke / 60.0;
+        Ok(StateVector {
+            r: [mrt * ux * RE_KM, mrt * uy * RE_KM, mrt * uz * RE_KM],
+            v: [
+                (mvt * ux + rvdot * vx) * vkmpersec,
+                (mvt * uy + rvdot * vy) * vkmpersec,
+                (mvt * uz + rvdot * vz) * vkmpersec,
+            ],
+        })
+    }
+}
+
+pub fn julian_date(t: DateTime<Utc>) -> f64 {
+    t.timestamp_millis() as f64 / 86_400_000.0 + 2_440_587.5
+}
+
+/// Среднее звёздное время по Гринвичу (IAU-82), радианы; UT1 принимается равным UTC.
+pub fn gmst(t: DateTime<Utc>) -> f64 {
+    let tut1 = (julian_date(t) - 2_451_545.0) / 36_525.0;
+    let sec = -6.2e-6 * tut1.powi(3) + 0.093104 * tut1 * tut1
+        + (876600.0 * 3600.0 + 8640184.812866) * tut1 + 67310.54841;
+    (sec.to_radians() / 240.0).rem_euclid(TWO_PI)
+}
+
+/// TEME -> вращающаяся с Землёй система (без учёта движения полюса), км.
+pub fn teme_to_ecef(r: [f64; 3], t: DateTime<Utc>) -> [f64; 3] {
+    let (s, c) = gmst(t).sin_cos();
+    [c * r[0] + s * r[1], -s * r[0] + c * r[1], r[2]]
+}
+
+/// Геодезические широта/долгота (градусы) и высота над эллипсоидом WGS-84 (км).
+pub fn ecef_to_geodetic(r: [f64; 3]) -> (f64, f64, f64) {
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let p = (r[0] * r[0] + r[1] * r[1]).sqrt();
+    let lon = r[1].atan2(r[0]);
+    let mut lat = r[2].atan2(p * (1.0 - e2));
+    let mut h = 0.0;
+    for _ in 0..5 {
+        let n = WGS84_A_KM / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        h = p / lat.cos() - n;
+        lat = r[2].atan2(p * (1.0 - e2 * n / (n + h)));
+    }
+    (lat.to_degrees(), lon.to_degrees(), h)
+}
+
+/// Диаметр области видимости спутника на поверхности (как `footprint` у wheretheiss.at), км.
+pub fn footprint_km(altitude_km: f64) -> f64 {
+    let r = WGS84_A_KM;
+    2.0 * r * (r / (r + altitude_km)).acos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vallado et al. 2006, SGP4-VER.TLE / tcppver.out (WGS-72)
+    const L1_00005: &str = "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753";
+    const L2_00005: &str = "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667";
+
+    /// tsince (мин), r (км), v (км/с)
+    const VECTORS_00005: &[(f64, [f64; 3], [f64; 3])] = &[
+        (0.0, [7022.46529266, -1400.08296755, 0.03995155], [1.893841015, 6.405893759, 4.534807250]),
+        (360.0, [-7154.03120202, -3783.17682504, -3536.19412294], [4.741887409, -4.151817765, -2.093935425]),
+        (720.0, [-7134.59340119, 6531.68641334, 3260.27186483], [-4.113793027, -2.911922039, -2.557327851]),
+        (1080.0, [5568.53901181, 4492.06992591, 3863.87641983], [-4.209106476, 5.159719888, 2.744852980]),
+        (1440.0, [-938.55923943, -6268.18748831, -4294.02924751], [7.536105209, -0.427127707, 0.989878080]),
+    ];
+
+    #[test]
+    fn matches_vallado_vectors_00005() {
+        let tle = Tle::parse(None, L1_00005, L2_00005).unwrap();
+        let model = Sgp4::new(&tle).unwrap();
+        for &(t, r, v) in VECTORS_00005 {
+            let s = model.propagate(t).unwrap();
+            for i in 0..3 {
+                assert!((s.r[i] - r[i]).abs() < 1e-6, "t={t} r[{i}]: {} vs {}", s.r[i], r[i]);
+                assert!((s.v[i] - v[i]).abs() < 1e-9, "t={t} v[{i}]: {} vs {}", s.v[i], v[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_vallado_epoch_06251() {
+        // низкая орбита с заметным торможением (B* 1.28e-4)
+        let tle = Tle::parse(
+            None,
+            "1 06251U 62025E   06176.82412014  .00008885  00000-0  12808-3 0  3985",
+            "2 06251  58.0579  54.0425 0030035 139.1568 221.1854 15.56387291  6774",
+        ).unwrap();
+        let s = Sgp4::new(&tle).unwrap().propagate(0.0).unwrap();
+        let (r, v) = ([3988.31022699, 5498.96657235, 0.90055879], [-3.290032738, 2.357652820, 6.496623475]);
+        for i in 0..3 {
+            assert!((s.r[i] - r[i]).abs() < 1e-6, "r[{i}]: {} vs {}", s.r[i], r[i]);
+            assert!((s.v[i] - v[i]).abs() < 1e-9, "v[{i}]: {} vs {}", s.v[i], v[i]);
+        }
+    }
+
+    #[test]
+    fn parses_elements_and_epoch() {
+        let tle = Tle::parse(Some(" VANGUARD 1 "), L1_00005, L2_00005).unwrap();
+        assert_eq!(tle.name.as_deref(), Some("VANGUARD 1"));
+        assert_eq!(tle.norad_id, 5);
+        assert_eq!(tle.epoch.to_rfc3339(), "2000-06-27T18:50:19.733568+00:00");
+        assert!((tle.bstar - 2.8098e-5).abs() < 1e-15);
+        assert!((tle.eccentricity - 0.1859667).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let broken = format!("{}4", &L1_00005[..68]);
+        assert!(matches!(Tle::parse(None, &broken, L2_00005), Err(Sgp4Error::Tle(_))));
+        let broken = format!("{}0", &L2_00005[..68]);
+        assert!(matches!(Tle::parse(None, L1_00005, &broken), Err(Sgp4Error::Tle(_))));
+    }
+
+    #[test]
+    fn rejects_swapped_lines() {
+        assert!(Tle::parse(None, L2_00005, L1_00005).is_err());
+    }
+
+    #[test]
+    fn finds_two_and_three_line_sets() {
+        let two = format!("{L1_00005}\n{L2_00005}\n");
+        let tle = Tle::find_in(&two, None).unwrap();
+        assert_eq!((tle.norad_id, tle.name), (5, None));
+
+        let three = format!("0 VANGUARD 1\r\n{L1_00005}\r\n{L2_00005}\r\n");
+        let tle = Tle::find_in(&three, Some(5)).unwrap();
+        assert_eq!(tle.name.as_deref(), Some("VANGUARD 1"));
+
+        assert!(Tle::find_in(&three, Some(25544)).is_err());
+        assert!(Tle::find_in("no elements here", None).is_err());
+    }
+
+    #[test]
+    fn skips_bad_sets_before_the_good_one() {
+        let bad = format!("{}4", &L1_00005[..68]);
+        let text = format!("BROKEN\n{bad}\n{L2_00005}\nVANGUARD 1\n{L1_00005}\n{L2_00005}\n");
+        let tle = Tle::find_in(&text, Some(5)).unwrap();
+        assert_eq!(tle.name.as_deref(), Some("VANGUARD 1"));
+        assert_eq!(Tle::find_in(&text, None).unwrap().name.as_deref(), Some("VANGUARD 1"));
+
+        // подходящего нет — ошибка испорченного набора, а не «не найдено»
+        let only_bad = format!("{bad}\n{L2_00005}\n");
+        let err = Tle::find_in(&only_bad, None).unwrap_err().to_string();
+        assert!(!err.contains("no element set found"), "{err}");
+    }
+
+    #[test]
+    fn reads_implied_decimals() {
+        assert_eq!(implied_decimal(" 28098-4").unwrap(), 0.28098e-4);
+        assert_eq!(implied_decimal("-11606-4").unwrap(), -0.11606e-4);
+        assert_eq!(implied_decimal(" 00000-0").unwrap(), 0.0);
+        assert_eq!(implied_decimal("").unwrap(), 0.0);
+    }
+}
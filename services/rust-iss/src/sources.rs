@@ -0,0 +1,324 @@
+//! Внешние ленты, складываемые в space_cache: общий трейт источника и реестр.
+//! Новая лента — одна реализация `Source` со своими `name()` и `defaults()` и строка в `all()`;
+//! секция в sources.toml нужна, только чтобы заменить значения по умолчанию.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{config::{Auth, SourceConfig}, donki, http::Http, neo, quota::Priority};
+
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// Ключ в space_cache, в sources.toml и в параметре `src`.
+    fn name(&self) -> &'static str;
+    /// Настройки, если в sources.toml нет своей секции.
+    fn defaults(&self) -> SourceConfig;
+    fn request(&self, cfg: &SourceConfig, client: &reqwest::Client) -> reqwest::RequestBuilder {
+        cfg.request(client)
+    }
+    /// Проверка ответа перед записью.
+    fn parse(&self, body: Value) -> anyhow::Result<Value> {
+        Ok(body)
+    }
+    /// Возвращает число записанных строк.
+    async fn store(&self, pool: &PgPool, cfg: &SourceConfig, payload: Value) -> anyhow::Result<u64> {
+        store_cache(pool, self.name(), cfg, payload).await
+    }
+}
+
+/// Все ленты модуля, включая выключенные.
+pub fn all() -> Vec<Arc<dyn Source>> {
+    vec![
+        Arc::new(Apod),
+        Arc::new(NeoFeed),
+        Arc::new(Donki::new("flr", 3600, 60, Priority::Normal)),
+        Arc::new(Donki::new("cme", 3600, 60, Priority::Normal)),
+        // геомагнитные бури, с Kp
+        Arc::new(Donki::new("gst", 3600, 60, Priority::Normal)),
+        // солнечные энергичные частицы
+        Arc::new(Donki::new("sep", 10800, 120, Priority::Normal)),
+        // межпланетные ударные волны
+        Arc::new(Donki::new("ips", 10800, 120, Priority::Normal)),
+        // высокоскоростные потоки солнечного ветра
+        Arc::new(Donki::new("hss", 21600, 300, Priority::Low)),
+        // усиления радиационных поясов
+        Arc::new(Donki::new("rbe", 21600, 300, Priority::Low)),
+        // пересечения магнитопаузы
+        Arc::new(Donki::new("mpc", 21600, 300, Priority::Low)),
+        Arc::new(DonkiNotifications),
+        Arc::new(SpacexNext),
+    ]
+}
+
+/// Запись в space_cache с учётом retention_days источника.
+async fn store_cache(pool: &PgPool, source: &str, cfg: &SourceConfig, payload: Value) -> anyhow::Result<u64> {
+    write_cache(pool, source, payload).await?;
+    if let Some(days) = cfg.retention_days {
+        sqlx::query("DELETE FROM space_cache WHERE source = $1 AND fetched_at < now() - make_interval(days => $2)")
+            .bind(source).bind(days as i32).execute(pool).await?;
+    }
+    Ok(1)
+}
+
+/// Один цикл: запрос, проверка, запись; число записанных строк.
+/// Если источник ответил 304, ничего не пишем.
+pub async fn run(feed: &Feed, http: &Http, pool: &PgPool) -> anyhow::Result<u64> {
+    let (src, cfg) = (&feed.source, &feed.cfg);
+    let Some((resp, validators)) = http.send_conditional(src.request(cfg, &http.client()), cfg.nasa()).await? else {
+        return Ok(0);
+    };
+    if !resp.status().is_success() {
+        anyhow::bail!("{} request status {}", src.name(), resp.status());
+    }
+    let body: Value = resp.json().await.map_err(reqwest::Error::without_url)?;
+    let payload = src.parse(body)?;
+    let written = src.store(pool, cfg, payload).await?;
+    http.remember(validators).await?;
+    Ok(written)
+}
+
+pub async fn write_cache(pool: &PgPool, source: &str, payload: Value) -> anyhow::Result<()> {
+    sqlx::query("INSERT INTO space_cache(source, payload) VALUES ($1,$2)")
+        .bind(source).bind(payload).execute(pool).await?;
+    Ok(())
+}
+
+fn last_days(n: u64) -> (String, String) {
+    let to = Utc::now().date_naive();
+    let from = to - chrono::Days::new(n);
+    (from.to_string(), to.to_string())
+}
+
+fn next_days(n: u64) -> (String, String) {
+    let from = Utc::now().date_naive();
+    let to = from + chrono::Days::new(n);
+    (from.to_string(), to.to_string())
+}
+
+/// Настройки ленты api.nasa.gov с ключом из пула.
+fn nasa_defaults(url: &str, every_seconds: u64, jitter_seconds: u64, priority: Priority) -> SourceConfig {
+    SourceConfig {
+        url: Some(url.to_string()),
+        auth: Auth::NasaKey,
+        priority,
+        every_seconds: Some(every_seconds),
+        jitter_seconds,
+        ..SourceConfig::default()
+    }
+}
+
+/* ---------- Реестр ---------- */
+
+/// Лента с действующими настройками.
+#[derive(Clone)]
+pub struct Feed {
+    source: Arc<dyn Source>,
+    cfg: SourceConfig,
+}
+
+impl Feed {
+    pub fn name(&self) -> &'static str {
+        self.source.name()
+    }
+
+    pub fn config(&self) -> &SourceConfig {
+        &self.cfg
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Registry {
+    feeds: Vec<Feed>,
+}
+
+impl Registry {
+    /// Включённые ленты из конфигурации.
+    pub fn from_config(feeds: &BTreeMap<String, SourceConfig>) -> Self {
+        let feeds = all().into_iter()
+            .filter_map(|source| {
+                let cfg = feeds.get(source.name()).filter(|cfg| cfg.enabled)?.clone();
+                Some(Feed { source, cfg })
+            })
+            .collect();
+        Self { feeds }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Feed> {
+        self.feeds.iter().find(|f| f.name() == name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.feeds.iter().map(Feed::name).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Feed> {
+        self.feeds.iter()
+    }
+}
+
+/* ---------- Источники ---------- */
+
+pub struct Apod;
+
+#[async_trait]
+impl Source for Apod {
+    fn name(&self) -> &'static str { "apod" }
+    fn defaults(&self) -> SourceConfig {
+        SourceConfig {
+            query: [("thumbs".to_string(), "true".to_string())].into(),
+            ..nasa_defaults("https://api.nasa.gov/planetary/apod", 43200, 300, Priority::Low)
+        }
+    }
+    fn parse(&self, body: Value) -> anyhow::Result<Value> {
+        if body.get("url").is_none() && body.get("date").is_none() {
+            anyhow::bail!("apod: response has neither url nor date");
+        }
+        Ok(body)
+    }
+}
+
+pub struct NeoFeed;
+
+#[async_trait]
+impl Source for NeoFeed {
+    fn name(&self) -> &'static str { "neo" }
+    fn defaults(&self) -> SourceConfig {
+        nasa_defaults("https://api.nasa.gov/neo/rest/v1/feed", 7200, 120, Priority::Normal)
+    }
+    /// Предстоящая неделя — максимальное окно NeoWs и окно /neo/approaches по умолчанию.
+    fn request(&self, cfg: &SourceConfig, client: &reqwest::Client) -> reqwest::RequestBuilder {
+        let (start, end) = next_days(7);
+        cfg.request(client).query(&[("start_date", start), ("end_date", end)])
+    }
+    fn parse(&self, body: Value) -> anyhow::Result<Value> {
+        if !body.get("near_earth_objects").is_some_and(Value::is_object) {
+            anyhow::bail!("neo: response without near_earth_objects");
+        }
+        Ok(body)
+    }
+    /// Сырой ответ — в space_cache, объекты и сближения — в neo_objects / neo_close_approaches.
+    async fn store(&self, pool: &PgPool, cfg: &SourceConfig, payload: Value) -> anyhow::Result<u64> {
+        let typed = neo::store_feed(pool, &payload).await?;
+        Ok(typed + store_cache(pool, self.name(), cfg, payload).await?)
+    }
+}
+
+/// DONKI отдаёт события одного типа за окно дат: FLR, CME, GST, ...
+pub struct Donki {
+    name: &'static str,
+    every_seconds: u64,
+    jitter_seconds: u64,
+    priority: Priority,
+}
+
+impl Donki {
+    fn new(name: &'static str, every_seconds: u64, jitter_seconds: u64, priority: Priority) -> Self {
+        Self { name, every_seconds, jitter_seconds, priority }
+    }
+}
+
+#[async_trait]
+impl Source for Donki {
+    fn name(&self) -> &'static str { self.name }
+    fn defaults(&self) -> SourceConfig {
+        let url = format!("https://api.nasa.gov/DONKI/{}", self.name.to_uppercase());
+        nasa_defaults(&url, self.every_seconds, self.jitter_seconds, self.priority)
+    }
+    fn request(&self, cfg: &SourceConfig, client: &reqwest::Client) -> reqwest::RequestBuilder {
+        let (from, to) = last_days(5);
+        cfg.request(client).query(&[("startDate", from), ("endDate", to)])
+    }
+    fn parse(&self, body: Value) -> anyhow::Result<Value> {
+        if !body.is_array() {
+            anyhow::bail!("{}: expected an array of events", self.name);
+        }
+        Ok(body)
+    }
+    /// Сырой ответ — в space_cache, события — в donki_events.
+    async fn store(&self, pool: &PgPool, cfg: &SourceConfig, payload: Value) -> anyhow::Result<u64> {
+        let typed = donki::store_events(pool, &self.name.to_uppercase(), &payload).await?;
+        Ok(typed + store_cache(pool, self.name, cfg, payload).await?)
+    }
+}
+
+/// Лента уведомлений DONKI (сводки, предупреждения) за то же окно.
+pub struct DonkiNotifications;
+
+#[async_trait]
+impl Source for DonkiNotifications {
+    fn name(&self) -> &'static str { "notifications" }
+    fn defaults(&self) -> SourceConfig {
+        SourceConfig {
+            query: [("type".to_string(), "all".to_string())].into(),
+            ..nasa_defaults("https://api.nasa.gov/DONKI/notifications", 1800, 60, Priority::Normal)
+        }
+    }
+    fn request(&self, cfg: &SourceConfig, client: &reqwest::Client) -> reqwest::RequestBuilder {
+        let (from, to) = last_days(5);
+        cfg.request(client).query(&[("startDate", from), ("endDate", to)])
+    }
+    fn parse(&self, body: Value) -> anyhow::Result<Value> {
+        if !body.is_array() {
+            anyhow::bail!("notifications: expected an array of messages");
+        }
+        Ok(body)
+    }
+    async fn store(&self, pool: &PgPool, cfg: &SourceConfig, payload: Value) -> anyhow::Result<u64> {
+        let typed = donki::store_notifications(pool, &payload).await?;
+        Ok(typed + store_cache(pool, self.name(), cfg, payload).await?)
+    }
+}
+
+pub struct SpacexNext;
+
+#[async_trait]
+impl Source for SpacexNext {
+    fn name(&self) -> &'static str { "spacex" }
+    fn defaults(&self) -> SourceConfig {
+        SourceConfig {
+            url: Some("https://api.spacexdata.com/v4/launches/next".to_string()),
+            every_seconds: Some(3600),
+            jitter_seconds: 60,
+            ..SourceConfig::default()
+        }
+    }
+    fn parse(&self, body: Value) -> anyhow::Result<Value> {
+        if body.get("id").is_none() {
+            anyhow::bail!("spacex: response without launch id");
+        }
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feeds_have_unique_names_and_usable_defaults() {
+        let feeds = all();
+        for (i, src) in feeds.iter().enumerate() {
+            assert!(feeds[..i].iter().all(|s| s.name() != src.name()), "duplicate feed '{}'", src.name());
+            let cfg = src.defaults();
+            assert!(cfg.url.is_some(), "{}: no url", src.name());
+            assert!(cfg.enabled && cfg.schedule().is_ok(), "{}: bad schedule", src.name());
+        }
+    }
+
+    #[test]
+    fn registry_keeps_enabled_configured_feeds() {
+        let mut feeds: BTreeMap<String, SourceConfig> =
+            all().iter().map(|s| (s.name().to_string(), s.defaults())).collect();
+        feeds.get_mut("hss").unwrap().enabled = false;
+        feeds.remove("rbe");
+        let registry = Registry::from_config(&feeds);
+        assert!(registry.get("apod").is_some());
+        assert!(registry.get("hss").is_none());
+        assert!(registry.get("rbe").is_none());
+        assert_eq!(registry.names().len(), all().len() - 2);
+    }
+}